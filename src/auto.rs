@@ -0,0 +1,453 @@
+//! Automatic brightness driven by an ambient light sensor.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use backend::{BacklightBackend, Sysfs};
+use iio::LightSensor;
+use state;
+use {Brightness, Error};
+
+/// Maps ambient illuminance to a brightness percentage by interpolating
+/// between `(lux, percent)` points.  Interpolation happens on a logarithmic
+/// lux axis, which matches how the eye adapts to light.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LuxCurve {
+	points: Vec<(f64, f64)>,
+}
+
+impl LuxCurve {
+	/// Create a curve from `(lux, percent)` points.  Lux values must be
+	/// non-negative and strictly increasing, and percentages between 0
+	/// and 100 and never decreasing.
+	pub fn new(points: Vec<(f64, f64)>) -> Result<Self, Error> {
+		if points.is_empty() {
+			return Err(Error::InvalidCurve("a lux curve needs at least one point"));
+		}
+		let valid = |&(lux, percent): &(f64, f64)| lux >= 0.0 && lux.is_finite() && (0.0..=100.0).contains(&percent);
+		if !points.iter().all(valid) {
+			return Err(Error::InvalidCurve("lux must be non-negative and percentages between 0 and 100"));
+		}
+		for pair in points.windows(2) {
+			if pair[1].0 <= pair[0].0 || pair[1].1 < pair[0].1 {
+				return Err(Error::InvalidCurve("lux curve points must be increasing"));
+			}
+		}
+		Ok(LuxCurve { points })
+	}
+
+	/// The points of the curve.
+	pub fn points(&self) -> &[(f64, f64)] {
+		&self.points
+	}
+
+	/// Return the brightness percentage for `lux`.  Illuminance outside
+	/// the curve takes the percentage of the nearest end.
+	pub fn percent(&self, lux: f64) -> f64 {
+		let position = log_lux(lux);
+		let first = self.points[0];
+		let last = self.points[self.points.len() - 1];
+		if position <= log_lux(first.0) {
+			return first.1;
+		}
+		if position >= log_lux(last.0) {
+			return last.1;
+		}
+		for pair in self.points.windows(2) {
+			let (a, b) = (pair[0], pair[1]);
+			if position <= log_lux(b.0) {
+				let t = (position - log_lux(a.0)) / (log_lux(b.0) - log_lux(a.0));
+				return a.1 + t * (b.1 - a.1);
+			}
+		}
+		last.1
+	}
+}
+
+impl Default for LuxCurve {
+	/// A curve suitable for a typical laptop or tablet panel, from 10% in
+	/// the dark to 100% in daylight.
+	fn default() -> Self {
+		LuxCurve {
+			points: vec![(0.0, 10.0), (10.0, 25.0), (100.0, 45.0), (1000.0, 75.0), (10000.0, 100.0)],
+		}
+	}
+}
+
+/// Position of `lux` on the logarithmic axis used for interpolation.
+/// One is added so that complete darkness is representable.
+pub(crate) fn log_lux(lux: f64) -> f64 {
+	(lux.max(0.0) + 1.0).log10()
+}
+
+/// Corrections to a [`LuxCurve`] learned from the user's manual changes.
+///
+/// Illuminance is divided into bands one decade wide (0-9 lux, 9-99 lux and
+/// so on), and each band has its own offset in percent that is added to the
+/// curve.  Every time the user picks a different brightness, the offset of
+/// the band they were in moves part of the way towards their choice, so
+/// that over repeated corrections the mapping converges on what they like.
+///
+/// Preferences are stored as a small text file with one `band offset` pair
+/// per line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preferences {
+	offsets: BTreeMap<i32, f64>,
+	rate: f64,
+}
+
+impl Preferences {
+	/// Preferences without any corrections, moving an offset halfway
+	/// towards each new choice.
+	pub fn new() -> Self {
+		Preferences {
+			offsets: BTreeMap::new(),
+			rate: 0.5,
+		}
+	}
+
+	/// Read preferences saved by [`save()`].  A file that does not exist
+	/// yet gives preferences without any corrections.
+	///
+	/// [`save()`]: #method.save
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+		let path = path.as_ref();
+		let content = match fs::read_to_string(path) {
+			Ok(content) => content,
+			Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Preferences::new()),
+			Err(err) => return Err(Error::from_io(err, path)),
+		};
+		let mut preferences = Preferences::new();
+		for line in content.lines().map(str::trim) {
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let mut fields = line.split_whitespace();
+			let entry = match (fields.next(), fields.next(), fields.next()) {
+				(Some(band), Some(offset), None) => band.parse::<i32>().ok().zip(offset.parse::<f64>().ok()),
+				_ => None,
+			};
+			match entry {
+				Some((band, offset)) if offset.is_finite() => {
+					preferences.offsets.insert(band, offset.clamp(-100.0, 100.0));
+				}
+				_ => {
+					return Err(Error::MalformedAttribute {
+						path: path.to_path_buf(),
+						content: line.to_string(),
+					})
+				}
+			}
+		}
+		Ok(preferences)
+	}
+
+	/// Write the preferences to `path`.  The file is replaced atomically,
+	/// so an interrupted save never loses what was learned before.
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+		let path = path.as_ref();
+		let mut content = String::from("# backlight auto-brightness preferences: band offset\n");
+		for (band, offset) in &self.offsets {
+			let _ = writeln!(content, "{} {}", band, offset);
+		}
+		state::replace(path, &content)
+	}
+
+	/// Set how far, between 0 and 1, an offset moves towards each new
+	/// choice.  1 adopts the latest choice outright.
+	pub fn set_rate(&mut self, rate: f64) {
+		self.rate = if rate.is_nan() { 0.5 } else { rate.clamp(0.0, 1.0) };
+	}
+
+	/// Return how far an offset moves towards each new choice.
+	pub fn rate(&self) -> f64 {
+		self.rate
+	}
+
+	/// Return the correction, in percent, for illuminance `lux`.
+	pub fn offset(&self, lux: f64) -> f64 {
+		self.offsets.get(&band(lux)).cloned().unwrap_or(0.0)
+	}
+
+	/// Record that the user chose `chosen` percent at illuminance `lux`,
+	/// where the uncorrected curve gives `predicted` percent.
+	pub fn learn(&mut self, lux: f64, predicted: f64, chosen: f64) {
+		let offset = self.offsets.entry(band(lux)).or_insert(0.0);
+		let error = chosen - (predicted + *offset);
+		*offset = (*offset + self.rate * error).clamp(-100.0, 100.0);
+	}
+
+	/// Return the percentage for `lux`: `curve`'s value plus the correction.
+	pub fn percent(&self, curve: &LuxCurve, lux: f64) -> f64 {
+		(curve.percent(lux) + self.offset(lux)).clamp(0.0, 100.0)
+	}
+
+	/// Forget all corrections.
+	pub fn clear(&mut self) {
+		self.offsets.clear();
+	}
+}
+
+impl Default for Preferences {
+	fn default() -> Self {
+		Preferences::new()
+	}
+}
+
+/// The band of illuminance, one decade wide, that `lux` falls in.
+fn band(lux: f64) -> i32 {
+	log_lux(lux).floor() as i32
+}
+
+/// Sets a device's brightness from an ambient light sensor.
+///
+/// Each call to [`step()`] reads the sensor, maps the illuminance to a
+/// percentage through a [`LuxCurve`] and applies it with
+/// [`Brightness::set_percent()`], so the device's curve and limits are
+/// respected.  To avoid flicker from small fluctuations, a new percentage
+/// is only applied when it differs from the last one applied by at least
+/// the hysteresis.
+///
+/// When the brightness has been changed by someone else since the last
+/// step, the controller treats it as the user's choice: it adjusts its
+/// [`Preferences`] for the current illuminance, and leaves the brightness
+/// alone until the illuminance moves to another band.  See
+/// [`learn_into()`] for keeping what was learned across restarts.
+///
+/// [`step()`]: #method.step
+/// [`learn_into()`]: #method.learn_into
+/// [`Brightness::set_percent()`]: ../struct.Brightness.html#method.set_percent
+pub struct AutoBrightness<B: BacklightBackend = Sysfs> {
+	sensor: LightSensor,
+	brightness: Brightness<B>,
+	curve: LuxCurve,
+	preferences: Preferences,
+	store: Option<PathBuf>,
+	hysteresis: i32,
+	applied: Option<i32>,
+	observed: Option<i32>,
+	held: Option<i32>,
+}
+
+impl<B: BacklightBackend> AutoBrightness<B> {
+	/// Drive `brightness` from `sensor` using the default curve and a
+	/// hysteresis of 5%.
+	pub fn new(sensor: LightSensor, brightness: Brightness<B>) -> Self {
+		AutoBrightness {
+			sensor,
+			brightness,
+			curve: LuxCurve::default(),
+			preferences: Preferences::new(),
+			store: None,
+			hysteresis: 5,
+			applied: None,
+			observed: None,
+			held: None,
+		}
+	}
+
+	/// Use `curve` to map illuminance to brightness.
+	pub fn set_curve(&mut self, curve: LuxCurve) {
+		self.curve = curve;
+	}
+
+	/// Return the curve that maps illuminance to brightness.
+	pub fn curve(&self) -> &LuxCurve {
+		&self.curve
+	}
+
+	/// Use `preferences` to correct the curve.
+	pub fn set_preferences(&mut self, preferences: Preferences) {
+		self.preferences = preferences;
+	}
+
+	/// Return the corrections learned from the user.
+	pub fn preferences(&self) -> &Preferences {
+		&self.preferences
+	}
+
+	/// Load the preferences from `path` and save them back there whenever
+	/// the user's choice changes them.
+	pub fn learn_into<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+		self.preferences = Preferences::load(&path)?;
+		self.store = Some(path.as_ref().to_path_buf());
+		Ok(())
+	}
+
+	/// Set the smallest change, in percent, that will be applied.
+	pub fn set_hysteresis(&mut self, percent: i32) {
+		self.hysteresis = percent.max(0);
+	}
+
+	/// The sensor being read.
+	pub fn sensor(&self) -> &LightSensor {
+		&self.sensor
+	}
+
+	/// The device being driven.
+	pub fn brightness(&self) -> &Brightness<B> {
+		&self.brightness
+	}
+
+	/// The device being driven, e.g. to change its curve or limits.
+	pub fn brightness_mut(&mut self) -> &mut Brightness<B> {
+		&mut self.brightness
+	}
+
+	/// The percentage most recently applied by [`step()`], if any.
+	///
+	/// [`step()`]: #method.step
+	pub fn applied(&self) -> Option<i32> {
+		self.applied
+	}
+
+	/// Read the sensor once and update the brightness if needed.  Returns
+	/// the percentage applied, or `None` if the change was within the
+	/// hysteresis or the user has just chosen a brightness of their own.
+	pub fn step(&mut self) -> Result<Option<i32>, Error> {
+		let lux = self.sensor.read_lux()?;
+		if let Some(observed) = self.observed {
+			let current = self.brightness.get_percent()?;
+			if current != observed {
+				self.preferences.learn(lux, self.curve.percent(lux), f64::from(current));
+				if let Some(ref path) = self.store {
+					self.preferences.save(path)?;
+				}
+				self.applied = Some(current);
+				self.observed = Some(current);
+				self.held = Some(band(lux));
+				return Ok(None);
+			}
+		}
+		if self.held == Some(band(lux)) {
+			return Ok(None);
+		}
+		self.held = None;
+		let target = self.preferences.percent(&self.curve, lux).round() as i32;
+		if let Some(applied) = self.applied {
+			if (target - applied).abs() < self.hysteresis.max(1) {
+				return Ok(None);
+			}
+		}
+		self.brightness.set_percent(target)?;
+		self.applied = Some(target);
+		self.observed = Some(self.brightness.get_percent()?);
+		Ok(Some(target))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testing::FakeSysfs;
+	use BacklightType;
+
+	#[test]
+	fn lux_curve_interpolates_on_a_log_axis() {
+		let curve = LuxCurve::default();
+		assert_eq!(curve.percent(0.0), 10.0);
+		assert_eq!(curve.percent(10.0), 25.0);
+		assert_eq!(curve.percent(1e6), 100.0);
+		let between = curve.percent(30.0);
+		assert!(between > 25.0 && between < 45.0);
+	}
+
+	#[test]
+	fn lux_curve_rejects_invalid_points() {
+		assert!(LuxCurve::new(vec![]).is_err());
+		assert!(LuxCurve::new(vec![(-1.0, 10.0)]).is_err());
+		assert!(LuxCurve::new(vec![(0.0, 101.0)]).is_err());
+		assert!(LuxCurve::new(vec![(10.0, 10.0), (5.0, 20.0)]).is_err());
+		assert!(LuxCurve::new(vec![(0.0, 50.0), (10.0, 20.0)]).is_err());
+		assert!(LuxCurve::new(vec![(0.0, 20.0), (10.0, 50.0)]).is_ok());
+	}
+
+	#[test]
+	fn step_applies_the_curve_with_hysteresis() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let lcd = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+		let als = sysfs.add_light_sensor(0, 20, 0.5, 0.0).unwrap();
+
+		let sensor = LightSensor::find_in(sysfs.iio_root()).unwrap();
+		let br = Brightness::with_root(sysfs.backlight_root(), "lcd");
+		let mut auto = AutoBrightness::new(sensor, br);
+		assert_eq!(auto.step().unwrap(), Some(25));
+		assert_eq!(lcd.read("brightness"), "25");
+
+		als.write("in_illuminance_raw", 22);
+		assert_eq!(auto.step().unwrap(), None);
+		assert_eq!(lcd.read("brightness"), "25");
+
+		als.write("in_illuminance_raw", 200);
+		assert_eq!(auto.step().unwrap(), Some(45));
+		assert_eq!(auto.applied(), Some(45));
+	}
+
+	#[test]
+	fn preferences_move_towards_each_choice() {
+		let mut preferences = Preferences::new();
+		preferences.learn(11.0, 25.0, 65.0);
+		assert_eq!(preferences.offset(11.0), 20.0);
+		preferences.learn(11.0, 25.0, 65.0);
+		assert_eq!(preferences.offset(11.0), 30.0);
+		assert_eq!(preferences.offset(500.0), 0.0);
+		assert_eq!(preferences.percent(&LuxCurve::default(), 10.0), 55.0);
+
+		preferences.set_rate(1.0);
+		preferences.learn(11.0, 25.0, 30.0);
+		assert_eq!(preferences.offset(11.0), 5.0);
+	}
+
+	#[test]
+	fn preferences_round_trip_through_a_file() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let path = sysfs.path().join("preferences");
+		assert_eq!(Preferences::load(&path).unwrap(), Preferences::new());
+
+		let mut preferences = Preferences::new();
+		preferences.learn(11.0, 25.0, 60.0);
+		preferences.learn(500.0, 60.0, 50.0);
+		preferences.save(&path).unwrap();
+		assert_eq!(Preferences::load(&path).unwrap(), preferences);
+
+		fs::write(&path, "1 x\n").unwrap();
+		match Preferences::load(&path) {
+			Err(Error::MalformedAttribute { content, .. }) => assert_eq!(content, "1 x"),
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+
+	#[test]
+	fn step_learns_from_overrides() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let lcd = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+		let als = sysfs.add_light_sensor(0, 22, 0.5, 0.0).unwrap();
+		let store = sysfs.path().join("preferences");
+
+		let sensor = LightSensor::find_in(sysfs.iio_root()).unwrap();
+		let br = Brightness::with_root(sysfs.backlight_root(), "lcd");
+		let mut auto = AutoBrightness::new(sensor, br);
+		auto.learn_into(&store).unwrap();
+		assert!(auto.step().unwrap().is_some());
+
+		// The user prefers it brighter; the override is kept and learned.
+		lcd.write("brightness", 90);
+		assert_eq!(auto.step().unwrap(), None);
+		assert_eq!(auto.step().unwrap(), None);
+		assert_eq!(lcd.read("brightness"), "90");
+		assert!(auto.preferences().offset(11.0) > 30.0);
+		assert_eq!(&Preferences::load(&store).unwrap(), auto.preferences());
+
+		// In a brighter room the correction is not applied...
+		als.write("in_illuminance_raw", 200);
+		assert_eq!(auto.step().unwrap(), Some(45));
+
+		// ...but it is on returning to the same light level.
+		als.write("in_illuminance_raw", 22);
+		let corrected = auto.step().unwrap().unwrap();
+		assert!(corrected > 50 && corrected < 90);
+	}
+}
@@ -0,0 +1,270 @@
+//! The interface between [`Brightness`](../struct.Brightness.html) and the
+//! hardware it controls.
+//!
+//! `Brightness` implements percentages, curves, fades and power handling on
+//! top of a [`BacklightBackend`], which only has to read and write raw
+//! levels.  [`Sysfs`] drives backlight and LED class devices and
+//! [`Pwm`](../pwm/struct.Pwm.html) drives PWM channels; other hardware,
+//! such as monitors controlled over DDC/CI, can be supported by
+//! implementing the trait.
+
+use std::path::{Path, PathBuf};
+
+use sysfs;
+use {BlankState, Error};
+
+/// Raw access to a device whose brightness can be controlled.
+pub trait BacklightBackend {
+	/// Read the current brightness level.
+	fn read_level(&self) -> Result<i32, Error>;
+
+	/// Read the maximum brightness level.  [`Brightness`] rejects a
+	/// maximum that is not positive with [`Error::OutOfRange`], so
+	/// implementations need not check it.
+	///
+	/// [`Brightness`]: ../struct.Brightness.html
+	fn read_max(&self) -> Result<i32, Error>;
+
+	/// Write a new brightness level between 0 and the maximum.
+	fn write_level(&self, value: i32) -> Result<(), Error>;
+
+	/// Read the brightness level reported by the hardware.  Backends that
+	/// cannot tell it apart from the requested level return that instead.
+	fn read_actual_level(&self) -> Result<i32, Error> {
+		self.read_level()
+	}
+
+	/// Read the power state.  Backends without power control return
+	/// [`Error::Unsupported`].
+	fn read_power(&self) -> Result<BlankState, Error> {
+		Err(Error::Unsupported("power control"))
+	}
+
+	/// Change the power state.  Backends without power control return
+	/// [`Error::Unsupported`].
+	fn write_power(&self, _state: BlankState) -> Result<(), Error> {
+		Err(Error::Unsupported("power control"))
+	}
+}
+
+impl<B: BacklightBackend + ?Sized> BacklightBackend for Box<B> {
+	fn read_level(&self) -> Result<i32, Error> {
+		(**self).read_level()
+	}
+
+	fn read_max(&self) -> Result<i32, Error> {
+		(**self).read_max()
+	}
+
+	fn write_level(&self, value: i32) -> Result<(), Error> {
+		(**self).write_level(value)
+	}
+
+	fn read_actual_level(&self) -> Result<i32, Error> {
+		(**self).read_actual_level()
+	}
+
+	fn read_power(&self) -> Result<BlankState, Error> {
+		(**self).read_power()
+	}
+
+	fn write_power(&self, state: BlankState) -> Result<(), Error> {
+		(**self).write_power(state)
+	}
+}
+
+/// A backlight or LED class device directory in sysfs.
+#[derive(Debug, Clone)]
+pub struct Sysfs {
+	path: PathBuf,
+}
+
+impl Sysfs {
+	/// Use the device in the given directory, e.g.
+	/// /sys/class/backlight/intel_backlight.
+	pub fn new<P: AsRef<Path>>(path: P) -> Self {
+		Sysfs { path: path.as_ref().to_path_buf() }
+	}
+
+	/// The directory holding the device's attributes.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Read an attribute holding a single integer.
+	pub(crate) fn get(&self, filename: &str) -> Result<i32, Error> {
+		sysfs::get(&self.path, filename)
+	}
+
+	/// Read the trimmed content of an attribute.
+	pub(crate) fn read(&self, filename: &str) -> Result<String, Error> {
+		sysfs::read(&self.path, filename)
+	}
+
+	/// Write a value to an attribute.
+	pub(crate) fn set(&self, filename: &str, value: &str) -> Result<(), Error> {
+		sysfs::set(&self.path, filename, value)
+	}
+
+	/// Report an attribute that is missing from a device that exists, such
+	/// as `bl_power` on an LED, as an unsupported `operation`.
+	fn optional<T>(&self, result: Result<T, Error>, operation: &'static str) -> Result<T, Error> {
+		match result {
+			Err(Error::DeviceNotFound(_)) if self.path.is_dir() => Err(Error::Unsupported(operation)),
+			result => result,
+		}
+	}
+}
+
+impl BacklightBackend for Sysfs {
+	fn read_level(&self) -> Result<i32, Error> {
+		self.get("brightness")
+	}
+
+	fn read_max(&self) -> Result<i32, Error> {
+		self.get("max_brightness")
+	}
+
+	fn write_level(&self, value: i32) -> Result<(), Error> {
+		self.set("brightness", &value.to_string())
+	}
+
+	fn read_actual_level(&self) -> Result<i32, Error> {
+		self.optional(self.get("actual_brightness"), "actual brightness")
+	}
+
+	fn read_power(&self) -> Result<BlankState, Error> {
+		let value = self.optional(self.get("bl_power"), "power control")?;
+		match BlankState::from_value(value) {
+			Some(state) => Ok(state),
+			None => Err(Error::MalformedAttribute {
+				path: self.path.join("bl_power"),
+				content: value.to_string(),
+			}),
+		}
+	}
+
+	fn write_power(&self, state: BlankState) -> Result<(), Error> {
+		self.optional(self.set("bl_power", &state.value().to_string()), "power control")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::Cell;
+	use std::fs;
+
+	use super::*;
+	use testing::{Failure, FakeSysfs, MockBackend};
+	use Brightness;
+
+	/// A backend that only implements the required methods.
+	struct Minimal {
+		level: Cell<i32>,
+	}
+
+	impl BacklightBackend for Minimal {
+		fn read_level(&self) -> Result<i32, Error> {
+			Ok(self.level.get())
+		}
+
+		fn read_max(&self) -> Result<i32, Error> {
+			Ok(10)
+		}
+
+		fn write_level(&self, value: i32) -> Result<(), Error> {
+			self.level.set(value);
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn defaults_cover_the_optional_methods() {
+		let br = Brightness::from_backend(Minimal { level: Cell::new(3) });
+		assert!(br.set_percent(70).unwrap());
+		assert_eq!(br.get_brightness().unwrap(), 7);
+		assert_eq!(br.get_actual_brightness().unwrap(), 7);
+		match br.power_off() {
+			Err(Error::Unsupported("power control")) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		match br.is_powered() {
+			Err(Error::Unsupported(_)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+
+	#[test]
+	fn boxed_backends_forward_every_method() {
+		let mock = MockBackend::new(100);
+		mock.set_clamp(Some((0, 80)));
+		let backend: Box<dyn BacklightBackend> = Box::new(mock.clone());
+		let br = Brightness::from_backend(backend);
+
+		br.set_brightness(90).unwrap();
+		assert_eq!(br.get_brightness().unwrap(), 90);
+		assert_eq!(br.get_actual_brightness().unwrap(), 80);
+		assert_eq!(br.get_max_brightness().unwrap(), 100);
+		br.set_power(BlankState::Powerdown).unwrap();
+		assert_eq!(br.get_power().unwrap(), BlankState::Powerdown);
+		assert_eq!(mock.writes(), [90]);
+	}
+
+	#[test]
+	fn backend_failures_reach_the_caller() {
+		let mock = MockBackend::new(100);
+		let br = Brightness::from_backend(mock.clone());
+
+		mock.set_write_failure(Some(Failure::Rejected));
+		match br.set_brightness(50) {
+			Err(Error::WriteRejected { value, .. }) => assert_eq!(value, "50"),
+			other => panic!("unexpected {:?}", other),
+		}
+		mock.set_write_failure(None);
+
+		mock.set_read_failure(Some(Failure::PermissionDenied));
+		match br.get_brightness() {
+			Err(Error::PermissionDenied(_)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		mock.set_read_failure(Some(Failure::NotFound));
+		match br.get_percent() {
+			Err(Error::DeviceNotFound(_)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		assert!(mock.writes().is_empty());
+	}
+
+	#[test]
+	fn attributes_missing_from_leds_are_unsupported() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let led = sysfs.add_led("tpacpi::kbd_backlight", 2).unwrap();
+		let mut br = Brightness::with_root(sysfs.leds_root(), "tpacpi::kbd_backlight");
+
+		match br.get_actual_brightness() {
+			Err(Error::Unsupported("actual brightness")) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		match br.power_off() {
+			Err(Error::Unsupported("power control")) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		match br.is_powered() {
+			Err(Error::Unsupported("power control")) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		br.verify_writes(true);
+		match br.set_brightness(1) {
+			Err(Error::Unsupported("actual brightness")) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		assert_eq!(led.read("brightness"), "1");
+
+		// A device that has gone away is still reported as missing.
+		fs::remove_dir_all(led.path()).unwrap();
+		match br.get_actual_brightness() {
+			Err(Error::DeviceNotFound(_)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+}
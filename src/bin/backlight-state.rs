@@ -0,0 +1,180 @@
+// Copyright (C) 2020 Andy Pont <andy.pont@sdcsystems.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS
+// IN THE SOFTWARE.
+
+//! Save the brightness of backlights and keyboard backlights at shutdown and
+//! restore it at boot, for use from a service such as:
+//!
+//! ```text
+//! ExecStart=/usr/bin/backlight-state load
+//! ExecStop=/usr/bin/backlight-state save
+//! ```
+
+extern crate backlight;
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use backlight::{Brightness, Device, DeviceClass, Error, LedFunction, StateStore, STATE_DIR};
+
+const USAGE: &str = "usage: backlight-state [--state-dir DIR] [--sysfs DIR] [--min-percent N] load|save [CLASS:]DEVICE...";
+
+fn main() {
+	let mut state_dir = PathBuf::from(STATE_DIR);
+	let mut sysfs = PathBuf::from("/sys");
+	let mut min_percent = 5;
+	let mut positional = Vec::new();
+
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--state-dir" => state_dir = PathBuf::from(value(args.next())),
+			"--sysfs" => sysfs = PathBuf::from(value(args.next())),
+			"--min-percent" => min_percent = match value(args.next()).parse() {
+				Ok(percent) => percent,
+				Err(_) => usage(),
+			},
+			"-h" | "--help" => {
+				println!("{}", USAGE);
+				return;
+			}
+			_ if arg.starts_with('-') => usage(),
+			_ => positional.push(arg),
+		}
+	}
+	if positional.is_empty() {
+		usage();
+	}
+	let command = positional.remove(0);
+	if command != "save" && command != "load" {
+		usage();
+	}
+	let store = StateStore::with_dir(state_dir).min_percent(min_percent);
+
+	let devices = match find(&sysfs, &positional) {
+		Ok(devices) => devices,
+		Err(err) => {
+			eprintln!("backlight-state: {}", err);
+			process::exit(1);
+		}
+	};
+
+	let mut failed = false;
+	for device in &devices {
+		let result = if command == "save" {
+			store.save(device).map(Some)
+		} else {
+			store.restore(device)
+		};
+		match result {
+			Ok(Some(value)) => println!("{}: {} {}", device.id(), command, value),
+			Ok(None) => println!("{}: nothing saved", device.id()),
+			Err(err) => {
+				eprintln!("backlight-state: {}: {}", device.name(), err);
+				failed = true;
+			}
+		}
+	}
+	if failed {
+		process::exit(1);
+	}
+}
+
+/// The devices named on the command line, or every backlight and keyboard
+/// backlight if none were named.
+fn find(sysfs: &Path, names: &[String]) -> Result<Vec<Device>, Error> {
+	let backlights = scan(Brightness::list_in(sysfs.join("class/backlight")))?;
+	let leds = scan(Brightness::list_leds_in(sysfs.join("class/leds")))?;
+	if names.is_empty() {
+		let keyboards = leds.into_iter().filter(|d| d.led_function() == Some(LedFunction::KbdBacklight));
+		return Ok(backlights.into_iter().chain(keyboards).collect());
+	}
+
+	let mut devices = Vec::new();
+	for name in names {
+		let (class, name) = match name.find(':') {
+			Some(i) if &name[..i] == "leds" => (DeviceClass::Led, &name[i + 1..]),
+			Some(i) if &name[..i] == "backlight" => (DeviceClass::Backlight, &name[i + 1..]),
+			_ => (DeviceClass::Backlight, name.as_str()),
+		};
+		let (candidates, root) = match class {
+			DeviceClass::Backlight => (&backlights, sysfs.join("class/backlight")),
+			DeviceClass::Led => (&leds, sysfs.join("class/leds")),
+		};
+		match candidates.iter().find(|d| d.name() == name) {
+			Some(device) => devices.push(device.clone()),
+			None => return Err(Error::DeviceNotFound(root.join(name))),
+		}
+	}
+	Ok(devices)
+}
+
+/// The devices found by a scan.  A class directory that does not exist,
+/// e.g. on a machine without LEDs, has no devices; any other failure to
+/// read it is an error.
+fn scan(devices: Result<Vec<Device>, Error>) -> Result<Vec<Device>, Error> {
+	match devices {
+		Err(Error::DeviceNotFound(_)) => Ok(Vec::new()),
+		result => result,
+	}
+}
+
+fn value(arg: Option<String>) -> String {
+	match arg {
+		Some(value) => value,
+		None => usage(),
+	}
+}
+
+fn usage() -> ! {
+	eprintln!("{}", USAGE);
+	process::exit(2);
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::io;
+
+	use super::*;
+
+	#[test]
+	fn missing_class_directories_have_no_devices() {
+		let sysfs = env::temp_dir().join("backlight-state-no-sysfs");
+		assert!(find(&sysfs, &[]).unwrap().is_empty());
+		match find(&sysfs, &["leds:tpacpi::kbd_backlight".to_string()]) {
+			Err(Error::DeviceNotFound(path)) => assert_eq!(path, sysfs.join("class/leds/tpacpi::kbd_backlight")),
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+
+	#[test]
+	fn other_scan_failures_are_errors() {
+		let denied = Error::PermissionDenied(PathBuf::from("/sys/class/backlight"));
+		match scan(Err(denied)) {
+			Err(Error::PermissionDenied(_)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		match scan(Err(Error::Io(io::Error::from_raw_os_error(5)))) {
+			Err(Error::Io(_)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+}
@@ -0,0 +1,536 @@
+// Copyright (C) 2020 Andy Pont <andy.pont@sdcsystems.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS
+// IN THE SOFTWARE.
+
+//! Command line interface to the backlight crate.  Built with the `cli`
+//! cargo feature.
+
+extern crate structopt;
+use structopt::clap::AppSettings;
+use structopt::StructOpt;
+
+extern crate backlight;
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+
+use backlight::{BlankState, Brightness, Device, Easing, Error, FadeOutcome};
+use backlight::permissions::{self, Problem};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "backlight", about = "Query and control backlights and LEDs")]
+struct Opt {
+	/// The device to use, a backlight or an LED; defaults to the preferred backlight
+	#[structopt(short, long, global = true)]
+	device: Option<String>,
+
+	/// Print machine-readable JSON instead of text
+	#[structopt(short, long, global = true)]
+	json: bool,
+
+	/// The sysfs mount point
+	#[structopt(long, global = true, default_value = "/sys", parse(from_os_str))]
+	sysfs: PathBuf,
+
+	#[structopt(subcommand)]
+	command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+	/// List the backlight and LED devices
+	List,
+	/// Print the brightness
+	Get {
+		/// What to print: raw, percent or actual
+		#[structopt(default_value = "raw", possible_values = &["raw", "percent", "actual"])]
+		what: String,
+	},
+	/// Set the brightness, e.g. 120, 50%, +5% or -10
+	#[structopt(setting = AppSettings::AllowLeadingHyphen)]
+	Set {
+		value: Value,
+	},
+	/// Fade to a new brightness, e.g. 120, 50%, +5% or -10
+	#[structopt(setting = AppSettings::AllowLeadingHyphen)]
+	Fade {
+		value: Value,
+		/// Length of the fade in milliseconds
+		#[structopt(short = "t", long, default_value = "500")]
+		duration: u64,
+		/// Shape of the fade: linear, ease-in, ease-out or ease-in-out
+		#[structopt(short, long, default_value = "linear", parse(try_from_str = parse_easing))]
+		easing: Easing,
+	},
+	/// Print the power state, or turn the device on or off
+	Power {
+		#[structopt(possible_values = &["on", "off"])]
+		state: Option<String>,
+	},
+	/// Print a udev rule letting a group change the brightness of every
+	/// device, or of the one given with --device
+	UdevRule {
+		/// The group to give access to
+		#[structopt(short, long, default_value = permissions::DEFAULT_GROUP)]
+		group: String,
+	},
+	/// Explain whether, and why not, the brightness can be changed
+	Diagnose,
+}
+
+/// A brightness given on the command line.
+#[derive(Debug, Clone, Copy)]
+enum Value {
+	Raw(i32),
+	Percent(i32),
+	RelativeRaw(i32),
+	RelativePercent(i32),
+}
+
+impl FromStr for Value {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let relative = s.starts_with('+') || s.starts_with('-');
+		let (number, percent) = match s.strip_suffix('%') {
+			Some(number) => (number, true),
+			None => (s, false),
+		};
+		let number = match number.parse::<i32>() {
+			Ok(number) => number,
+			Err(_) => return Err(format!("invalid brightness '{}'", s)),
+		};
+		Ok(match (relative, percent) {
+			(false, false) => Value::Raw(number),
+			(false, true) => Value::Percent(number),
+			(true, false) => Value::RelativeRaw(number),
+			(true, true) => Value::RelativePercent(number),
+		})
+	}
+}
+
+fn parse_easing(s: &str) -> Result<Easing, String> {
+	match s {
+		"linear" => Ok(Easing::Linear),
+		"ease-in" => Ok(Easing::EaseIn),
+		"ease-out" => Ok(Easing::EaseOut),
+		"ease-in-out" => Ok(Easing::EaseInOut),
+		_ => Err(format!("unknown easing '{}'", s)),
+	}
+}
+
+fn main() {
+	let opt = Opt::from_args();
+	if let Err(err) = run(&opt) {
+		if opt.json {
+			println!("{{\"error\":{}}}", json_string(&err.to_string()));
+		} else {
+			eprintln!("backlight: {}", err);
+		}
+		process::exit(1);
+	}
+}
+
+fn run(opt: &Opt) -> Result<(), Error> {
+	let backlights = opt.sysfs.join("class/backlight");
+	let leds = opt.sysfs.join("class/leds");
+
+	if let Command::List = opt.command {
+		let devices = list(&backlights, &leds)?;
+		if opt.json {
+			let entries: Vec<String> = devices.iter().map(device_json).collect();
+			println!("[{}]", entries.join(","));
+		} else {
+			for device in &devices {
+				println!("{} {} ({}), max brightness {}", device.class(), device.name(),
+					device.kind(), device.max_brightness());
+			}
+		}
+		return Ok(());
+	}
+
+	if let Command::UdevRule { ref group } = opt.command {
+		let devices = match opt.device {
+			Some(ref name) => vec![find(&backlights, &leds, Some(name))?],
+			None => list(&backlights, &leds)?,
+		};
+		let rules = permissions::udev_rules(&devices, group);
+		if opt.json {
+			println!("{{\"path\":{},\"rules\":{}}}", json_string(permissions::UDEV_RULES_PATH), json_string(&rules));
+		} else {
+			print!("{}", rules);
+		}
+		return Ok(());
+	}
+
+	let device = find(&backlights, &leds, opt.device.as_deref())?;
+	let br = device.open();
+	let mut output = Output::new(opt.json, device.name());
+
+	match opt.command {
+		Command::List | Command::UdevRule { .. } => unreachable!(),
+		Command::Get { ref what } => {
+			let value = match what.as_str() {
+				"percent" => br.get_percent()?,
+				"actual" => br.get_actual_brightness()?,
+				_ => br.get_brightness()?,
+			};
+			output.field(what, value);
+		}
+		Command::Set { value } => {
+			match value {
+				Value::Raw(raw) => { br.set_brightness(raw)?; }
+				Value::Percent(percent) => { br.set_percent(percent)?; }
+				Value::RelativeRaw(delta) => { br.adjust_brightness(delta)?; }
+				Value::RelativePercent(delta) => { br.adjust_percent(delta)?; }
+			}
+			output.field("raw", br.get_brightness()?);
+			output.field("percent", br.get_percent()?);
+		}
+		Command::Fade { value, duration, easing } => {
+			let duration = Duration::from_millis(duration);
+			let outcome = match value {
+				Value::Raw(raw) => br.fade_to(raw, duration, easing)?,
+				Value::Percent(percent) => br.fade_to_percent(percent, duration, easing)?,
+				Value::RelativeRaw(delta) => {
+					let target = br.get_brightness()?.saturating_add(delta).clamp(0, device.max_brightness());
+					br.fade_to(target, duration, easing)?
+				}
+				Value::RelativePercent(delta) => {
+					let target = br.get_percent()?.saturating_add(delta).clamp(0, 100);
+					br.fade_to_percent(target, duration, easing)?
+				}
+			};
+			output.field("completed", outcome == FadeOutcome::Completed);
+			output.field("raw", br.get_brightness()?);
+			output.field("percent", br.get_percent()?);
+		}
+		Command::Power { ref state } => {
+			match state.as_deref() {
+				Some("on") => br.set_power(BlankState::Unblank)?,
+				Some("off") => br.set_power(BlankState::Powerdown)?,
+				_ => {}
+			}
+			output.field("power", if br.is_powered()? { "on" } else { "off" });
+		}
+		Command::Diagnose => {
+			let diagnosis = permissions::diagnose(device.path().join("brightness"))?;
+			let problem = match diagnosis.problem() {
+				None => "none",
+				Some(Problem::Missing) => "missing",
+				Some(Problem::OwnerNotWritable) => "owner-not-writable",
+				Some(Problem::GroupNotWritable) => "group-not-writable",
+				Some(Problem::NotInGroup) => "not-in-group",
+				Some(Problem::LoginRequired) => "login-required",
+			};
+			if opt.json {
+				output.field("writable", diagnosis.is_writable());
+				output.field("problem", problem);
+				output.field("group", diagnosis.group());
+				output.field("mode", format!("{:o}", diagnosis.mode()).as_str());
+			}
+			output.field("reason", diagnosis.reason().as_str());
+		}
+	}
+	output.print();
+	Ok(())
+}
+
+/// The device named on the command line, looked up among the backlights
+/// and then the LEDs, or the preferred backlight.
+fn find(backlights: &Path, leds: &Path, name: Option<&str>) -> Result<Device, Error> {
+	let name = match name {
+		Some(name) => name,
+		None => return Ok(Brightness::select_default_in(backlights)?.device().clone()),
+	};
+	if let Some(device) = lookup(backlights, Brightness::list_in(backlights), name)? {
+		return Ok(device);
+	}
+	if let Some(device) = lookup(leds, Brightness::list_leds_in(leds), name)? {
+		return Ok(device);
+	}
+	Err(Error::DeviceNotFound(backlights.join(name)))
+}
+
+/// The device called `name` among those scanned in `root`.
+fn lookup(root: &Path, devices: Result<Vec<Device>, Error>, name: &str) -> Result<Option<Device>, Error> {
+	if let Some(device) = scan(devices)?.into_iter().find(|device| device.name() == name) {
+		return Ok(Some(device));
+	}
+	// The scan skips devices it cannot read; opening one reports why.
+	if root.join(name).exists() {
+		Brightness::open_in(root, name)?;
+	}
+	Ok(None)
+}
+
+/// Every backlight followed by every LED.
+fn list(backlights: &Path, leds: &Path) -> Result<Vec<Device>, Error> {
+	let mut devices = scan(Brightness::list_in(backlights))?;
+	devices.extend(scan(Brightness::list_leds_in(leds))?);
+	Ok(devices)
+}
+
+/// The devices found by a scan.  A class directory that does not exist,
+/// e.g. on a machine without LEDs, has no devices; any other failure to
+/// read it is an error.
+fn scan(devices: Result<Vec<Device>, Error>) -> Result<Vec<Device>, Error> {
+	match devices {
+		Err(Error::DeviceNotFound(_)) => Ok(Vec::new()),
+		result => result,
+	}
+}
+
+/// The result of a command, printed as `name: value` lines or as a JSON
+/// object.
+struct Output {
+	json: bool,
+	fields: Vec<(String, String, String)>,
+}
+
+impl Output {
+	fn new(json: bool, device: &str) -> Self {
+		let mut output = Output { json, fields: Vec::new() };
+		output.field("device", device);
+		output
+	}
+
+	fn field<T: JsonValue>(&mut self, name: &str, value: T) {
+		self.fields.push((name.to_string(), value.text(), value.json()));
+	}
+
+	fn print(&self) {
+		if self.json {
+			let fields: Vec<String> = self.fields.iter()
+				.map(|(name, _, json)| format!("{}:{}", json_string(name), json))
+				.collect();
+			println!("{{{}}}", fields.join(","));
+		} else if self.fields.len() == 2 {
+			// A single result, e.g. from `get`, is printed on its own so
+			// that it can be used directly in a shell script.
+			println!("{}", self.fields[1].1);
+		} else {
+			for (name, text, _) in &self.fields[1..] {
+				println!("{}: {}", name, text);
+			}
+		}
+	}
+}
+
+/// A value that can be printed as text or as JSON.
+trait JsonValue {
+	fn text(&self) -> String;
+	fn json(&self) -> String;
+}
+
+impl JsonValue for i32 {
+	fn text(&self) -> String {
+		self.to_string()
+	}
+
+	fn json(&self) -> String {
+		self.to_string()
+	}
+}
+
+impl JsonValue for bool {
+	fn text(&self) -> String {
+		self.to_string()
+	}
+
+	fn json(&self) -> String {
+		self.to_string()
+	}
+}
+
+impl JsonValue for &str {
+	fn text(&self) -> String {
+		self.to_string()
+	}
+
+	fn json(&self) -> String {
+		json_string(self)
+	}
+}
+
+fn device_json(device: &Device) -> String {
+	let mut fields = vec![
+		format!("\"name\":{}", json_string(device.name())),
+		format!("\"class\":{}", json_string(&device.class().to_string())),
+		format!("\"type\":{}", json_string(&device.kind().to_string())),
+		format!("\"id\":{}", json_string(&device.id())),
+		format!("\"path\":{}", json_string(&device.path().to_string_lossy())),
+		format!("\"max_brightness\":{}", device.max_brightness()),
+	];
+	if let Ok(value) = device.open().get_brightness() {
+		fields.push(format!("\"brightness\":{}", value));
+	}
+	format!("{{{}}}", fields.join(","))
+}
+
+fn json_string(s: &str) -> String {
+	let mut out = String::from("\"");
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); }
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use std::env;
+	use std::fs;
+
+	use backlight::DeviceClass;
+
+	use super::*;
+
+	/// An empty directory standing in for /sys.
+	fn sysfs(test: &str) -> PathBuf {
+		let root = env::temp_dir().join(format!("backlight-cli-{}-{}", process::id(), test));
+		let _ = fs::remove_dir_all(&root);
+		fs::create_dir_all(root.join("class")).unwrap();
+		root
+	}
+
+	fn add(root: &Path, class: &str, name: &str, max_brightness: &str) {
+		let path = root.join("class").join(class).join(name);
+		fs::create_dir_all(&path).unwrap();
+		fs::write(path.join("max_brightness"), max_brightness).unwrap();
+		fs::write(path.join("brightness"), "0").unwrap();
+	}
+
+	#[test]
+	fn values_are_raw_or_percent_and_absolute_or_relative() {
+		match "120".parse() {
+			Ok(Value::Raw(120)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		match "50%".parse() {
+			Ok(Value::Percent(50)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		match "+5%".parse() {
+			Ok(Value::RelativePercent(5)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		match "-10".parse() {
+			Ok(Value::RelativeRaw(-10)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		for s in &["abc", "", "%", "5%%", "50 %"] {
+			assert_eq!(s.parse::<Value>().unwrap_err(), format!("invalid brightness '{}'", s));
+		}
+	}
+
+	#[test]
+	fn negative_values_are_not_taken_for_flags() {
+		let opt = Opt::from_iter_safe(&["backlight", "set", "-10"]).unwrap();
+		match opt.command {
+			Command::Set { value: Value::RelativeRaw(-10) } => {}
+			other => panic!("unexpected {:?}", other),
+		}
+
+		let opt = Opt::from_iter_safe(&["backlight", "fade", "-5%", "-e", "ease-out", "-t", "200"]).unwrap();
+		match opt.command {
+			Command::Fade { value: Value::RelativePercent(-5), duration: 200, easing: Easing::EaseOut } => {}
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+
+	#[test]
+	fn easings_are_parsed_by_name() {
+		assert_eq!(parse_easing("linear"), Ok(Easing::Linear));
+		assert_eq!(parse_easing("ease-in"), Ok(Easing::EaseIn));
+		assert_eq!(parse_easing("ease-in-out"), Ok(Easing::EaseInOut));
+		assert_eq!(parse_easing("bounce"), Err("unknown easing 'bounce'".to_string()));
+	}
+
+	#[test]
+	fn json_strings_are_escaped() {
+		assert_eq!(json_string("intel_backlight"), "\"intel_backlight\"");
+		assert_eq!(json_string("a \"b\" \\ c\n"), "\"a \\\"b\\\" \\\\ c\\n\"");
+		assert_eq!(json_string("\t\u{1}"), "\"\\u0009\\u0001\"");
+		assert_eq!(json_string("é"), "\"é\"");
+	}
+
+	#[test]
+	fn find_looks_up_backlights_then_leds() {
+		let root = sysfs("find");
+		let (backlights, leds) = (root.join("class/backlight"), root.join("class/leds"));
+		add(&root, "backlight", "intel_backlight", "7500");
+		add(&root, "leds", "tpacpi::kbd_backlight", "2");
+
+		assert_eq!(find(&backlights, &leds, None).unwrap().name(), "intel_backlight");
+		assert_eq!(find(&backlights, &leds, Some("intel_backlight")).unwrap().class(), DeviceClass::Backlight);
+		assert_eq!(find(&backlights, &leds, Some("tpacpi::kbd_backlight")).unwrap().class(), DeviceClass::Led);
+		match find(&backlights, &leds, Some("acpi_video0")) {
+			Err(Error::DeviceNotFound(path)) => assert_eq!(path, backlights.join("acpi_video0")),
+			other => panic!("unexpected {:?}", other),
+		}
+		assert_eq!(list(&backlights, &leds).unwrap().len(), 2);
+		fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn find_reports_why_a_device_cannot_be_read() {
+		let root = sysfs("unreadable");
+		let (backlights, leds) = (root.join("class/backlight"), root.join("class/leds"));
+		add(&root, "leds", "input3::capslock", "lots");
+
+		match find(&backlights, &leds, Some("input3::capslock")) {
+			Err(Error::MalformedAttribute { path, content }) => {
+				assert_eq!(path, leds.join("input3::capslock/max_brightness"));
+				assert_eq!(content, "lots");
+			}
+			other => panic!("unexpected {:?}", other),
+		}
+		fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn only_missing_class_directories_count_as_empty() {
+		let root = sysfs("scan");
+		let (backlights, leds) = (root.join("class/backlight"), root.join("class/leds"));
+		assert!(list(&backlights, &leds).unwrap().is_empty());
+
+		add(&root, "backlight", "intel_backlight", "7500");
+		fs::write(&leds, "").unwrap();
+		match list(&backlights, &leds) {
+			Err(Error::Io(_)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		match find(&backlights, &leds, Some("tpacpi::kbd_backlight")) {
+			Err(Error::Io(_)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		assert_eq!(find(&backlights, &leds, Some("intel_backlight")).unwrap().name(), "intel_backlight");
+		fs::remove_dir_all(&root).unwrap();
+	}
+}
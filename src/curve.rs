@@ -0,0 +1,208 @@
+//! Response curves mapping user facing percentages to raw brightness.
+//!
+//! A [`Curve`] converts a percentage between 0 and 100 into the fraction of
+//! the maximum brightness to write, and back again.  Every curve provided
+//! here is strictly increasing, so converting a percentage to a fraction and
+//! back yields the original percentage.
+//!
+//! That guarantee covers the curve only.  The fraction is rounded to one of
+//! the device's raw levels before it is written, so a device with fewer
+//! levels than percentages cannot represent every percentage, and reading
+//! the brightness back gives the percentage of the level written.
+
+use scale;
+use Error;
+
+/// Maps between percentages and fractions of the maximum brightness.
+pub trait Curve {
+	/// Convert a percentage between 0 and 100 into a fraction of the
+	/// maximum brightness between 0 and 1.
+	fn to_fraction(&self, percent: f64) -> f64;
+
+	/// Convert a fraction of the maximum brightness between 0 and 1 back
+	/// into a percentage between 0 and 100.  This must be the inverse of
+	/// [`to_fraction()`](#tymethod.to_fraction).
+	fn to_percent(&self, fraction: f64) -> f64;
+}
+
+/// The percentage is the fraction of the maximum brightness.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Linear;
+
+impl Curve for Linear {
+	fn to_fraction(&self, percent: f64) -> f64 {
+		percent / 100.0
+	}
+
+	fn to_percent(&self, fraction: f64) -> f64 {
+		fraction * 100.0
+	}
+}
+
+/// The percentage is the CIE 1931 lightness, which approximates how bright
+/// a linear panel appears to the eye.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cie1931;
+
+impl Curve for Cie1931 {
+	fn to_fraction(&self, percent: f64) -> f64 {
+		scale::lightness_to_luminance(percent)
+	}
+
+	fn to_percent(&self, fraction: f64) -> f64 {
+		scale::luminance_to_lightness(fraction)
+	}
+}
+
+/// A power law: the fraction is the percentage raised to `exponent`.
+#[derive(Debug, Clone, Copy)]
+pub struct Gamma {
+	exponent: f64,
+}
+
+impl Gamma {
+	/// Create a gamma curve.  Exponents above 1 give finer control at the
+	/// dark end; `exponent` must be positive.
+	pub fn new(exponent: f64) -> Result<Self, Error> {
+		if !(exponent > 0.0 && exponent.is_finite()) {
+			return Err(Error::InvalidCurve("gamma exponent must be positive"));
+		}
+		Ok(Gamma { exponent })
+	}
+}
+
+impl Curve for Gamma {
+	fn to_fraction(&self, percent: f64) -> f64 {
+		(percent / 100.0).powf(self.exponent)
+	}
+
+	fn to_percent(&self, fraction: f64) -> f64 {
+		fraction.powf(1.0 / self.exponent) * 100.0
+	}
+}
+
+/// An exponential response, so that each percentage step changes the light
+/// output by the same ratio: `(base^(p/100) - 1) / (base - 1)`.
+#[derive(Debug, Clone, Copy)]
+pub struct Logarithmic {
+	base: f64,
+}
+
+impl Logarithmic {
+	/// Create a logarithmic curve.  Larger bases dedicate more of the
+	/// range to dim levels; `base` must be greater than 1.
+	pub fn new(base: f64) -> Result<Self, Error> {
+		if !(base > 1.0 && base.is_finite()) {
+			return Err(Error::InvalidCurve("logarithmic base must be greater than 1"));
+		}
+		Ok(Logarithmic { base })
+	}
+}
+
+impl Curve for Logarithmic {
+	fn to_fraction(&self, percent: f64) -> f64 {
+		(self.base.powf(percent / 100.0) - 1.0) / (self.base - 1.0)
+	}
+
+	fn to_percent(&self, fraction: f64) -> f64 {
+		(fraction * (self.base - 1.0) + 1.0).log(self.base) * 100.0
+	}
+}
+
+/// A piecewise linear lookup table, e.g. measured with a photometer.
+#[derive(Debug, Clone)]
+pub struct Table {
+	points: Vec<(f64, f64)>,
+}
+
+impl Table {
+	/// Create a table from `(percent, fraction)` points.  The points must
+	/// start at 0%, end at 100%, and be strictly increasing in both values.
+	pub fn new(points: Vec<(f64, f64)>) -> Result<Self, Error> {
+		if points.len() < 2 {
+			return Err(Error::InvalidCurve("a table needs at least two points"));
+		}
+		if points[0].0 != 0.0 || points[points.len() - 1].0 != 100.0 {
+			return Err(Error::InvalidCurve("a table must cover 0% to 100%"));
+		}
+		for pair in points.windows(2) {
+			if pair[1].0 <= pair[0].0 || pair[1].1 <= pair[0].1 {
+				return Err(Error::InvalidCurve("table points must be strictly increasing"));
+			}
+		}
+		if points.iter().any(|&(_, fraction)| !(0.0..=1.0).contains(&fraction)) {
+			return Err(Error::InvalidCurve("table fractions must be between 0 and 1"));
+		}
+		Ok(Table { points })
+	}
+
+	/// Linearly interpolate `x` along the table, where `from` and `to`
+	/// select the input and output coordinates of each point.
+	fn interpolate<F, T>(&self, x: f64, from: F, to: T) -> f64
+		where F: Fn(&(f64, f64)) -> f64, T: Fn(&(f64, f64)) -> f64
+	{
+		let first = &self.points[0];
+		let last = &self.points[self.points.len() - 1];
+		if x <= from(first) {
+			return to(first);
+		}
+		if x >= from(last) {
+			return to(last);
+		}
+		for pair in self.points.windows(2) {
+			let (a, b) = (&pair[0], &pair[1]);
+			if x <= from(b) {
+				let t = (x - from(a)) / (from(b) - from(a));
+				return to(a) + t * (to(b) - to(a));
+			}
+		}
+		to(last)
+	}
+}
+
+impl Curve for Table {
+	fn to_fraction(&self, percent: f64) -> f64 {
+		self.interpolate(percent, |p| p.0, |p| p.1)
+	}
+
+	fn to_percent(&self, fraction: f64) -> f64 {
+		self.interpolate(fraction, |p| p.1, |p| p.0)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_round_trip<C: Curve>(curve: &C) {
+		for step in 0..=200 {
+			let percent = f64::from(step) / 2.0;
+			let fraction = curve.to_fraction(percent);
+			assert!((0.0..=1.0).contains(&fraction), "{} maps to {}", percent, fraction);
+			let back = curve.to_percent(fraction);
+			assert!((back - percent).abs() < 1e-9, "{} comes back as {}", percent, back);
+		}
+	}
+
+	#[test]
+	fn built_in_curves_round_trip() {
+		assert_round_trip(&Linear);
+		assert_round_trip(&Cie1931);
+		assert_round_trip(&Gamma::new(2.2).unwrap());
+		assert_round_trip(&Gamma::new(0.5).unwrap());
+		assert_round_trip(&Logarithmic::new(10.0).unwrap());
+		assert_round_trip(&Logarithmic::new(1000.0).unwrap());
+		assert_round_trip(&Table::new(vec![(0.0, 0.0), (50.0, 0.2), (100.0, 1.0)]).unwrap());
+	}
+
+	#[test]
+	fn invalid_parameters_are_rejected() {
+		assert!(Gamma::new(0.0).is_err());
+		assert!(Gamma::new(f64::NAN).is_err());
+		assert!(Logarithmic::new(1.0).is_err());
+		assert!(Table::new(vec![(0.0, 0.0)]).is_err());
+		assert!(Table::new(vec![(0.0, 0.0), (90.0, 1.0)]).is_err());
+		assert!(Table::new(vec![(0.0, 0.5), (100.0, 0.5)]).is_err());
+		assert!(Table::new(vec![(0.0, 0.0), (100.0, 1.5)]).is_err());
+	}
+}
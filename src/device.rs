@@ -0,0 +1,428 @@
+//! Discovery of the backlight and LED devices published in sysfs.
+
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use {Brightness, Error};
+
+/// The directory that the kernel publishes backlight devices in.
+pub const SYSFS_BACKLIGHT: &str = "/sys/class/backlight";
+
+/// The directory that the kernel publishes LED devices, such as keyboard
+/// backlights, in.
+pub const SYSFS_LEDS: &str = "/sys/class/leds";
+
+/// The sysfs class a device belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceClass {
+	/// A display backlight in /sys/class/backlight.
+	Backlight,
+	/// An LED, such as a keyboard backlight, in /sys/class/leds.
+	Led,
+}
+
+impl fmt::Display for DeviceClass {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match *self {
+			DeviceClass::Backlight => "backlight",
+			DeviceClass::Led => "leds",
+		};
+		f.write_str(name)
+	}
+}
+
+/// The function of an LED, taken from the last part of its
+/// `devicename:color:function` name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LedFunction {
+	/// Keyboard backlight, e.g. `tpacpi::kbd_backlight`.
+	KbdBacklight,
+	/// Caps lock indicator.
+	CapsLock,
+	/// Num lock indicator.
+	NumLock,
+	/// Scroll lock indicator.
+	ScrollLock,
+	/// Speaker mute indicator.
+	Mute,
+	/// Microphone mute indicator.
+	MicMute,
+	/// General status indicator.
+	Status,
+	/// Power indicator.
+	Power,
+	/// Any other function.
+	Other(String),
+}
+
+impl LedFunction {
+	/// Return the function encoded in an LED device name.
+	pub fn from_name(name: &str) -> Self {
+		let function = name.rsplit(':').next().unwrap_or(name);
+		match function {
+			"kbd_backlight" => LedFunction::KbdBacklight,
+			"capslock" => LedFunction::CapsLock,
+			"numlock" => LedFunction::NumLock,
+			"scrolllock" => LedFunction::ScrollLock,
+			"mute" => LedFunction::Mute,
+			"micmute" => LedFunction::MicMute,
+			"status" => LedFunction::Status,
+			"power" => LedFunction::Power,
+			other => LedFunction::Other(other.to_string()),
+		}
+	}
+}
+
+impl fmt::Display for LedFunction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match *self {
+			LedFunction::KbdBacklight => "kbd_backlight",
+			LedFunction::CapsLock => "capslock",
+			LedFunction::NumLock => "numlock",
+			LedFunction::ScrollLock => "scrolllock",
+			LedFunction::Mute => "mute",
+			LedFunction::MicMute => "micmute",
+			LedFunction::Status => "status",
+			LedFunction::Power => "power",
+			LedFunction::Other(ref other) => other,
+		};
+		f.write_str(name)
+	}
+}
+
+/// The interface type reported by the `type` attribute of a backlight.
+/// LEDs have no such attribute and are always [`Unknown`].
+///
+/// [`Unknown`]: #variant.Unknown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BacklightType {
+	/// Controlled through a standard firmware interface, e.g. ACPI.
+	Firmware,
+	/// Controlled through a platform specific interface.
+	Platform,
+	/// Controlled by writing directly to hardware registers.
+	Raw,
+	/// The `type` attribute is missing or holds an unrecognised value.
+	Unknown,
+}
+
+impl BacklightType {
+	/// Parse the content of a `type` attribute.
+	pub fn parse(value: &str) -> Self {
+		match value.trim() {
+			"firmware" => BacklightType::Firmware,
+			"platform" => BacklightType::Platform,
+			"raw" => BacklightType::Raw,
+			_ => BacklightType::Unknown,
+		}
+	}
+
+	/// Rank used when choosing a default device; higher is preferred.
+	/// This follows the order used by systemd-backlight and GNOME.
+	fn priority(self) -> u8 {
+		match self {
+			BacklightType::Firmware => 3,
+			BacklightType::Platform => 2,
+			BacklightType::Raw => 1,
+			BacklightType::Unknown => 0,
+		}
+	}
+}
+
+impl fmt::Display for BacklightType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match *self {
+			BacklightType::Firmware => "firmware",
+			BacklightType::Platform => "platform",
+			BacklightType::Raw => "raw",
+			BacklightType::Unknown => "unknown",
+		};
+		f.write_str(name)
+	}
+}
+
+/// Description of a single backlight or LED device found in sysfs.
+#[derive(Debug, Clone)]
+pub struct Device {
+	name: String,
+	path: PathBuf,
+	class: DeviceClass,
+	kind: BacklightType,
+	max_brightness: i32,
+}
+
+impl Device {
+	/// Read the description of the device in the given sysfs directory.
+	pub(crate) fn from_path(path: &Path, class: DeviceClass) -> Result<Self, Error> {
+		let name = match path.file_name() {
+			Some(name) => name.to_string_lossy().into_owned(),
+			None => return Err(Error::DeviceNotFound(path.to_path_buf())),
+		};
+		let path = match fs::canonicalize(path) {
+			Ok(resolved) => resolved,
+			Err(err) => return Err(Error::from_io(err, path)),
+		};
+		let kind = match (class, fs::read_to_string(path.join("type"))) {
+			(DeviceClass::Backlight, Ok(value)) => BacklightType::parse(&value),
+			_ => BacklightType::Unknown,
+		};
+		let max_brightness = Brightness::from_path(&path).refresh()?;
+
+		Ok(Device {
+			name,
+			path,
+			class,
+			kind,
+			max_brightness,
+		})
+	}
+
+	/// The name of the device within its class directory.
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// Whether the device is a backlight or an LED.
+	pub fn class(&self) -> DeviceClass {
+		self.class
+	}
+
+	/// The function of an LED device, or `None` for backlights.
+	pub fn led_function(&self) -> Option<LedFunction> {
+		match self.class {
+			DeviceClass::Led => Some(LedFunction::from_name(&self.name)),
+			DeviceClass::Backlight => None,
+		}
+	}
+
+	/// The sysfs directory of the device with all symbolic links resolved.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// A stable identity for the device, made of the path of the hardware
+	/// it belongs to below /sys/devices, its class and its name, e.g.
+	/// `pci0000:00/0000:00:02.0/drm/card0/card0-eDP-1:backlight:intel_backlight`.
+	/// Unlike the name alone, it tells apart devices of the same name on
+	/// different hardware.
+	pub fn id(&self) -> String {
+		let parent = self.path.parent().unwrap_or(&self.path);
+		let names: Vec<String> = parent.components().filter_map(|component| match component {
+			Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+			_ => None,
+		}).collect();
+		let start = names.iter().position(|name| name == "devices").map_or(0, |i| i + 1);
+		format!("{}:{}:{}", names[start..].join("/"), self.class, self.name)
+	}
+
+	/// The interface type of the device.
+	pub fn kind(&self) -> BacklightType {
+		self.kind
+	}
+
+	/// The maximum brightness supported by the device.
+	pub fn max_brightness(&self) -> i32 {
+		self.max_brightness
+	}
+
+	/// Open the device for reading and writing its brightness.
+	pub fn open(&self) -> Brightness {
+		Brightness::from_device(&self.path, self.max_brightness)
+	}
+}
+
+/// The device chosen as the default backlight, along with the devices
+/// that were passed over.
+#[derive(Debug, Clone)]
+pub struct Selection {
+	device: Device,
+	others: Vec<Device>,
+}
+
+impl Selection {
+	/// Choose the preferred device from a list.  Devices are ranked by type,
+	/// firmware over platform over raw, and ties are broken by name.
+	pub fn choose(devices: Vec<Device>) -> Option<Self> {
+		let mut devices = devices;
+		devices.sort_by(|a, b| b.kind.priority().cmp(&a.kind.priority()).then_with(|| a.name.cmp(&b.name)));
+		if devices.is_empty() {
+			return None;
+		}
+		let device = devices.remove(0);
+		Some(Selection {
+			device,
+			others: devices,
+		})
+	}
+
+	/// The chosen device.
+	pub fn device(&self) -> &Device {
+		&self.device
+	}
+
+	/// The devices that were not chosen, in order of preference.
+	pub fn others(&self) -> &[Device] {
+		&self.others
+	}
+
+	/// A human readable explanation of why the device was chosen.
+	pub fn reason(&self) -> String {
+		let chosen = &self.device;
+		if self.others.is_empty() {
+			return format!("{} is the only backlight device", chosen.name);
+		}
+
+		let (tied, lower): (Vec<&Device>, Vec<&Device>) = self.others.iter()
+			.partition(|d| d.kind == chosen.kind);
+		let mut reason = format!("{} has type {}", chosen.name, chosen.kind);
+		if !lower.is_empty() {
+			let lower: Vec<String> = lower.iter().map(|d| format!("{} ({})", d.name, d.kind)).collect();
+			reason.push_str(&format!(", which is preferred over {}", lower.join(", ")));
+		}
+		if !tied.is_empty() {
+			let tied: Vec<&str> = tied.iter().map(|d| d.name.as_str()).collect();
+			reason.push_str(&format!("; it sorts first by name among devices of the same type ({})", tied.join(", ")));
+		}
+		reason
+	}
+}
+
+impl fmt::Display for Selection {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.reason())
+	}
+}
+
+/// Return a description of every device in the given class directory,
+/// sorted by name.  Entries that cannot be read are skipped.
+pub(crate) fn scan(root: &Path, class: DeviceClass) -> Result<Vec<Device>, Error> {
+	let mut devices = Vec::new();
+	let entries = match fs::read_dir(root) {
+		Ok(entries) => entries,
+		Err(err) => return Err(Error::from_io(err, root)),
+	};
+	for entry in entries {
+		if let Ok(device) = Device::from_path(&entry?.path(), class) {
+			devices.push(device);
+		}
+	}
+	devices.sort_by(|a, b| a.name.cmp(&b.name));
+	Ok(devices)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::*;
+	use testing::FakeSysfs;
+
+	#[test]
+	fn list_describes_each_device_sorted_by_name() {
+		let sysfs = FakeSysfs::new().unwrap();
+		sysfs.add_backlight("intel_backlight", BacklightType::Raw, 7500).unwrap();
+		sysfs.add_backlight("acpi_video0", BacklightType::Firmware, 15).unwrap();
+
+		let devices = Brightness::list_in(sysfs.backlight_root()).unwrap();
+		let names: Vec<&str> = devices.iter().map(|device| device.name()).collect();
+		assert_eq!(names, ["acpi_video0", "intel_backlight"]);
+
+		let device = &devices[1];
+		assert_eq!(device.class(), DeviceClass::Backlight);
+		assert_eq!(device.kind(), BacklightType::Raw);
+		assert_eq!(device.max_brightness(), 7500);
+		assert_eq!(device.path(), fs::canonicalize(sysfs.backlight_root().join("intel_backlight")).unwrap());
+		assert!(device.path().starts_with(fs::canonicalize(sysfs.path().join("devices")).unwrap()));
+	}
+
+	#[test]
+	fn list_skips_devices_that_cannot_be_read() {
+		let sysfs = FakeSysfs::new().unwrap();
+		sysfs.add_backlight("good", BacklightType::Platform, 100).unwrap();
+		sysfs.add_backlight("broken", BacklightType::Platform, 100).unwrap().remove("max_brightness");
+		sysfs.add_backlight("garbled", BacklightType::Platform, 100).unwrap().write("max_brightness", "lots");
+
+		let devices = Brightness::list_in(sysfs.backlight_root()).unwrap();
+		let names: Vec<&str> = devices.iter().map(|device| device.name()).collect();
+		assert_eq!(names, ["good"]);
+	}
+
+	#[test]
+	fn list_of_a_missing_directory_is_an_error() {
+		let sysfs = FakeSysfs::new().unwrap();
+		match Brightness::list_in(sysfs.path().join("class/nothing")) {
+			Err(Error::DeviceNotFound(_)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+
+	fn select(sysfs: &FakeSysfs) -> Selection {
+		Brightness::select_default_in(sysfs.backlight_root()).unwrap()
+	}
+
+	#[test]
+	fn choose_prefers_firmware_then_platform_then_raw() {
+		let sysfs = FakeSysfs::new().unwrap();
+		sysfs.add_backlight("a_raw", BacklightType::Raw, 100).unwrap();
+		sysfs.add_backlight("b_platform", BacklightType::Platform, 100).unwrap();
+		sysfs.add_backlight("c_firmware", BacklightType::Firmware, 100).unwrap();
+
+		let selection = select(&sysfs);
+		assert_eq!(selection.device().name(), "c_firmware");
+		let others: Vec<&str> = selection.others().iter().map(|device| device.name()).collect();
+		assert_eq!(others, ["b_platform", "a_raw"]);
+		assert_eq!(selection.reason(), "c_firmware has type firmware, which is preferred over b_platform (platform), a_raw (raw)");
+	}
+
+	#[test]
+	fn choose_breaks_ties_by_name() {
+		let sysfs = FakeSysfs::new().unwrap();
+		sysfs.add_backlight("nv_backlight", BacklightType::Raw, 100).unwrap();
+		sysfs.add_backlight("intel_backlight", BacklightType::Raw, 100).unwrap();
+		sysfs.add_backlight("amdgpu_bl0", BacklightType::Raw, 100).unwrap();
+
+		let selection = select(&sysfs);
+		assert_eq!(selection.device().name(), "amdgpu_bl0");
+		assert_eq!(selection.reason(), "amdgpu_bl0 has type raw; it sorts first by name among devices of the same type (intel_backlight, nv_backlight)");
+		assert_eq!(selection.to_string(), selection.reason());
+	}
+
+	#[test]
+	fn choose_explains_a_single_device() {
+		let sysfs = FakeSysfs::new().unwrap();
+		sysfs.add_backlight("intel_backlight", BacklightType::Raw, 100).unwrap();
+		assert_eq!(select(&sysfs).reason(), "intel_backlight is the only backlight device");
+		assert!(Selection::choose(Vec::new()).is_none());
+	}
+
+	#[test]
+	fn led_function_comes_from_the_last_part_of_the_name() {
+		assert_eq!(LedFunction::from_name("tpacpi::kbd_backlight"), LedFunction::KbdBacklight);
+		assert_eq!(LedFunction::from_name("input3::capslock"), LedFunction::CapsLock);
+		assert_eq!(LedFunction::from_name("platform::micmute"), LedFunction::MicMute);
+		assert_eq!(LedFunction::from_name("mute"), LedFunction::Mute);
+		assert_eq!(LedFunction::from_name("phy0-led"), LedFunction::Other("phy0-led".to_string()));
+		assert_eq!(LedFunction::from_name("white:status").to_string(), "status");
+	}
+
+	#[test]
+	fn leds_are_found_by_function() {
+		let sysfs = FakeSysfs::new().unwrap();
+		sysfs.add_led("tpacpi::kbd_backlight", 2).unwrap();
+		sysfs.add_led("input3::capslock", 1).unwrap();
+		sysfs.add_led("input7::capslock", 1).unwrap();
+
+		let leds = Brightness::list_leds_in(sysfs.leds_root()).unwrap();
+		let names: Vec<&str> = leds.iter().map(|led| led.name()).collect();
+		assert_eq!(names, ["input3::capslock", "input7::capslock", "tpacpi::kbd_backlight"]);
+		assert!(leds.iter().all(|led| led.class() == DeviceClass::Led));
+		assert_eq!(leds[2].max_brightness(), 2);
+
+		let keyboard = Brightness::find_leds_in(sysfs.leds_root(), &LedFunction::KbdBacklight).unwrap();
+		assert_eq!(keyboard.len(), 1);
+		assert_eq!(keyboard[0].led_function(), Some(LedFunction::KbdBacklight));
+		assert_eq!(Brightness::find_leds_in(sysfs.leds_root(), &LedFunction::CapsLock).unwrap().len(), 2);
+		assert!(Brightness::find_leds_in(sysfs.leds_root(), &LedFunction::Mute).unwrap().is_empty());
+	}
+}
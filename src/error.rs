@@ -0,0 +1,105 @@
+//! The error type returned by every fallible operation in this crate.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The ways in which reading or controlling a backlight can fail.
+#[derive(Debug)]
+pub enum Error {
+	/// The device, or the attribute at the given path, does not exist.
+	DeviceNotFound(PathBuf),
+	/// The process is not allowed to access the attribute at the given path.
+	PermissionDenied(PathBuf),
+	/// An attribute did not hold a value of the expected form.
+	MalformedAttribute {
+		/// The attribute that was read.
+		path: PathBuf,
+		/// What the attribute contained.
+		content: String,
+	},
+	/// A value was outside the range accepted by the operation.
+	OutOfRange {
+		/// The value that was requested.
+		value: i32,
+		/// The lowest accepted value.
+		min: i32,
+		/// The highest accepted value.
+		max: i32,
+	},
+	/// The kernel refused a value written to an attribute.
+	WriteRejected {
+		/// The attribute that was written.
+		path: PathBuf,
+		/// The value that was written.
+		value: String,
+		/// The error reported by the kernel.
+		source: io::Error,
+	},
+	/// The hardware reported a different brightness than was written.
+	Mismatch {
+		/// The brightness that was written.
+		requested: i32,
+		/// The brightness read back from `actual_brightness`.
+		actual: i32,
+	},
+	/// The parameters given for a brightness curve do not describe a
+	/// strictly increasing mapping.
+	InvalidCurve(&'static str),
+	/// The backend does not support the named operation.
+	Unsupported(&'static str),
+	/// Any other I/O error.
+	Io(io::Error),
+}
+
+impl Error {
+	/// Classify an error raised while opening or reading `path`.
+	pub(crate) fn from_io(err: io::Error, path: &Path) -> Self {
+		match err.kind() {
+			io::ErrorKind::NotFound => Error::DeviceNotFound(path.to_path_buf()),
+			io::ErrorKind::PermissionDenied => Error::PermissionDenied(path.to_path_buf()),
+			_ => Error::Io(err),
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Error::DeviceNotFound(ref path) => write!(f, "{} does not exist", path.display()),
+			Error::PermissionDenied(ref path) => write!(f, "permission denied accessing {}", path.display()),
+			Error::MalformedAttribute { ref path, ref content } => {
+				write!(f, "{} holds an unexpected value: {:?}", path.display(), content)
+			}
+			Error::OutOfRange { value, min, max } => {
+				write!(f, "{} is outside the range {} to {}", value, min, max)
+			}
+			Error::WriteRejected { ref path, ref value, ref source } => {
+				write!(f, "writing {:?} to {} was rejected: {}", value, path.display(), source)
+			}
+			Error::Mismatch { requested, actual } => {
+				write!(f, "requested brightness {} but the hardware reports {}", requested, actual)
+			}
+			Error::InvalidCurve(reason) => write!(f, "invalid brightness curve: {}", reason),
+			Error::Unsupported(operation) => write!(f, "the device does not support {}", operation),
+			Error::Io(ref err) => err.fmt(f),
+		}
+	}
+}
+
+impl error::Error for Error {
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		match *self {
+			Error::WriteRejected { ref source, .. } => Some(source),
+			Error::Io(ref err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(err: io::Error) -> Self {
+		Error::Io(err)
+	}
+}
@@ -0,0 +1,261 @@
+//! Smooth transitions between brightness levels.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The shape of a fade over time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+	/// Constant speed.
+	Linear,
+	/// Start slowly and accelerate.
+	EaseIn,
+	/// Start quickly and decelerate.
+	EaseOut,
+	/// Accelerate, then decelerate.
+	EaseInOut,
+}
+
+impl Easing {
+	/// Map the elapsed fraction of a fade, between 0 and 1, to the
+	/// fraction of the distance covered.
+	pub fn apply(self, t: f64) -> f64 {
+		let t = t.clamp(0.0, 1.0);
+		match self {
+			Easing::Linear => t,
+			Easing::EaseIn => t * t,
+			Easing::EaseOut => t * (2.0 - t),
+			Easing::EaseInOut => {
+				if t < 0.5 {
+					2.0 * t * t
+				} else {
+					1.0 - 2.0 * (1.0 - t) * (1.0 - t)
+				}
+			}
+		}
+	}
+}
+
+/// A source of time for fades.  Tests can substitute a virtual clock so
+/// that fades complete instantly and deterministically.
+pub trait Clock {
+	/// Time elapsed since an arbitrary, fixed starting point.
+	fn now(&self) -> Duration;
+
+	/// Wait for `duration` to pass.
+	fn sleep(&self, duration: Duration);
+}
+
+/// The real clock, backed by `Instant` and `thread::sleep`.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+	start: Instant,
+}
+
+impl SystemClock {
+	/// Create a clock whose starting point is now.
+	pub fn new() -> Self {
+		SystemClock { start: Instant::now() }
+	}
+}
+
+impl Default for SystemClock {
+	fn default() -> Self {
+		SystemClock::new()
+	}
+}
+
+impl Clock for SystemClock {
+	fn now(&self) -> Duration {
+		self.start.elapsed()
+	}
+
+	fn sleep(&self, duration: Duration) {
+		thread::sleep(duration);
+	}
+}
+
+/// A handle that stops a running fade.  Clones share the same state, so
+/// one can be moved to another thread to cancel a fade from there.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+	cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+	/// Create a token that has not been cancelled.
+	pub fn new() -> Self {
+		CancelToken::default()
+	}
+
+	/// Ask the fade to stop before its next step.
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::SeqCst);
+	}
+
+	/// Return true once [`cancel()`](#method.cancel) has been called.
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::SeqCst)
+	}
+}
+
+/// The parameters of a fade.
+#[derive(Debug, Clone)]
+pub struct Fade {
+	pub(crate) duration: Duration,
+	pub(crate) easing: Easing,
+	pub(crate) rate: u32,
+	pub(crate) cancel: Option<CancelToken>,
+}
+
+impl Fade {
+	/// A fade lasting `duration`, using linear easing at 60 steps per second.
+	pub fn new(duration: Duration) -> Self {
+		Fade {
+			duration,
+			easing: Easing::Linear,
+			rate: 60,
+			cancel: None,
+		}
+	}
+
+	/// Set the easing curve.
+	pub fn easing(mut self, easing: Easing) -> Self {
+		self.easing = easing;
+		self
+	}
+
+	/// Set the maximum number of steps per second.  Fewer steps are taken
+	/// when the device has too few levels to make use of them.
+	pub fn rate(mut self, steps_per_second: u32) -> Self {
+		self.rate = steps_per_second.max(1);
+		self
+	}
+
+	/// Stop the fade early when `token` is cancelled.
+	pub fn cancel_token(mut self, token: CancelToken) -> Self {
+		self.cancel = Some(token);
+		self
+	}
+
+	pub(crate) fn is_cancelled(&self) -> bool {
+		match self.cancel {
+			Some(ref token) => token.is_cancelled(),
+			None => false,
+		}
+	}
+
+	pub(crate) fn interval(&self) -> Duration {
+		Duration::from_secs(1) / self.rate
+	}
+}
+
+/// How a fade ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FadeOutcome {
+	/// The target was reached.
+	Completed,
+	/// The fade was cancelled, leaving the device at the given raw
+	/// brightness.
+	Cancelled(i32),
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::Cell;
+
+	use super::*;
+	use testing::{MockBackend, VirtualClock};
+	use Brightness;
+
+	const EASINGS: [Easing; 4] = [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut];
+
+	/// A virtual clock that cancels `token` after `sleeps` sleeps, i.e.
+	/// between two steps of a fade.
+	struct CancellingClock {
+		clock: VirtualClock,
+		token: CancelToken,
+		sleeps: Cell<u32>,
+	}
+
+	impl Clock for CancellingClock {
+		fn now(&self) -> Duration {
+			self.clock.now()
+		}
+
+		fn sleep(&self, duration: Duration) {
+			self.clock.sleep(duration);
+			self.sleeps.set(self.sleeps.get() - 1);
+			if self.sleeps.get() == 0 {
+				self.token.cancel();
+			}
+		}
+	}
+
+	#[test]
+	fn easings_start_at_zero_and_end_at_one() {
+		for &easing in &EASINGS {
+			assert_eq!(easing.apply(0.0), 0.0, "{:?}", easing);
+			assert_eq!(easing.apply(1.0), 1.0, "{:?}", easing);
+			assert_eq!(easing.apply(-0.5), 0.0, "{:?}", easing);
+			assert_eq!(easing.apply(1.5), 1.0, "{:?}", easing);
+		}
+		assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+		assert!(Easing::EaseIn.apply(0.25) < Easing::Linear.apply(0.25));
+		assert!(Easing::EaseOut.apply(0.25) > Easing::Linear.apply(0.25));
+	}
+
+	#[test]
+	fn easings_never_go_backwards() {
+		for &easing in &EASINGS {
+			let values: Vec<f64> = (0..=1000).map(|i| easing.apply(f64::from(i) / 1000.0)).collect();
+			assert!(values.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", easing);
+		}
+	}
+
+	#[test]
+	fn easing_shapes_the_steps_of_a_fade() {
+		let fade_with = |easing| {
+			let mock = MockBackend::new(100);
+			mock.set_level(0);
+			let br = Brightness::from_backend(mock.clone());
+			let fade = Fade::new(Duration::from_millis(100)).rate(40).easing(easing);
+			assert_eq!(br.fade(100, &fade, &VirtualClock::new()).unwrap(), FadeOutcome::Completed);
+			mock.writes()
+		};
+		assert_eq!(fade_with(Easing::Linear), [25, 50, 75, 100]);
+		assert_eq!(fade_with(Easing::EaseIn), [6, 25, 56, 100]);
+		assert_eq!(fade_with(Easing::EaseOut), [44, 75, 94, 100]);
+		assert_eq!(fade_with(Easing::EaseInOut), [13, 50, 87, 100]);
+	}
+
+	#[test]
+	fn cancelling_between_steps_stops_the_fade() {
+		let mock = MockBackend::new(100);
+		mock.set_level(0);
+		let br = Brightness::from_backend(mock.clone());
+		let token = CancelToken::new();
+		let clock = CancellingClock { clock: VirtualClock::new(), token: token.clone(), sleeps: Cell::new(3) };
+		let fade = Fade::new(Duration::from_millis(100)).rate(100).cancel_token(token.clone());
+
+		assert_eq!(br.fade(100, &fade, &clock).unwrap(), FadeOutcome::Cancelled(20));
+		assert!(token.is_cancelled());
+		assert_eq!(mock.writes(), [10, 20]);
+		assert_eq!(br.get_brightness().unwrap(), 20);
+		assert_eq!(clock.now(), Duration::from_millis(30));
+	}
+
+	#[test]
+	fn a_cancelled_token_prevents_any_write() {
+		let mock = MockBackend::new(100);
+		let br = Brightness::from_backend(mock.clone());
+		let token = CancelToken::new();
+		token.clone().cancel();
+		let fade = Fade::new(Duration::from_millis(100)).cancel_token(token);
+
+		assert_eq!(br.fade_percent(0, &fade, &VirtualClock::new()).unwrap(), FadeOutcome::Cancelled(100));
+		assert!(mock.writes().is_empty());
+	}
+}
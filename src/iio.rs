@@ -0,0 +1,123 @@
+//! Ambient light sensors published by the Industrial I/O subsystem.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sysfs;
+use Error;
+
+/// The directory that the kernel publishes IIO devices in.
+pub const SYSFS_IIO: &str = "/sys/bus/iio/devices";
+
+/// An ambient light sensor such as /sys/bus/iio/devices/iio:device0.
+#[derive(Debug, Clone)]
+pub struct LightSensor {
+	path: PathBuf,
+}
+
+impl LightSensor {
+	/// Create a new instance for the IIO device with the given name,
+	/// e.g. `iio:device0`.
+	pub fn new(device: &str) -> Self {
+		LightSensor::with_root(SYSFS_IIO, device)
+	}
+
+	/// Create a new instance for an IIO device that lives in `root` rather
+	/// than /sys/bus/iio/devices.
+	pub fn with_root<P: AsRef<Path>>(root: P, device: &str) -> Self {
+		LightSensor { path: root.as_ref().join(device) }
+	}
+
+	/// Return the first IIO device, by name, that measures illuminance.
+	pub fn find() -> Result<Self, Error> {
+		LightSensor::find_in(SYSFS_IIO)
+	}
+
+	/// Return the first IIO device in `root`, by name, that measures
+	/// illuminance.
+	pub fn find_in<P: AsRef<Path>>(root: P) -> Result<Self, Error> {
+		let root = root.as_ref();
+		let entries = match fs::read_dir(root) {
+			Ok(entries) => entries,
+			Err(err) => return Err(Error::from_io(err, root)),
+		};
+		let mut paths = Vec::new();
+		for entry in entries {
+			paths.push(entry?.path());
+		}
+		paths.sort();
+		match paths.into_iter().find(|path| LightSensor::is_light_sensor(path)) {
+			Some(path) => Ok(LightSensor { path }),
+			None => Err(Error::DeviceNotFound(root.join("in_illuminance_raw"))),
+		}
+	}
+
+	fn is_light_sensor(path: &Path) -> bool {
+		path.join("in_illuminance_input").exists() || path.join("in_illuminance_raw").exists()
+	}
+
+	/// The directory holding the sensor's attributes.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Return the illuminance in lux.  Sensors that publish a processed
+	/// `in_illuminance_input` are read directly; otherwise the value is
+	/// `(in_illuminance_raw + in_illuminance_offset) * in_illuminance_scale`,
+	/// where a missing offset or scale counts as 0 or 1.
+	pub fn read_lux(&self) -> Result<f64, Error> {
+		if let Some(lux) = self.read_optional("in_illuminance_input")? {
+			return Ok(lux);
+		}
+		let raw = self.read_float("in_illuminance_raw")?;
+		let offset = self.read_optional("in_illuminance_offset")?.unwrap_or(0.0);
+		let scale = self.read_optional("in_illuminance_scale")?.unwrap_or(1.0);
+		Ok(((raw + offset) * scale).max(0.0))
+	}
+
+	fn read_float(&self, filename: &str) -> Result<f64, Error> {
+		let content = sysfs::read(&self.path, filename)?;
+		match content.parse::<f64>() {
+			Ok(value) => Ok(value),
+			Err(_) => Err(Error::MalformedAttribute {
+				path: self.path.join(filename),
+				content,
+			}),
+		}
+	}
+
+	fn read_optional(&self, filename: &str) -> Result<Option<f64>, Error> {
+		match self.read_float(filename) {
+			Ok(value) => Ok(Some(value)),
+			Err(Error::DeviceNotFound(_)) => Ok(None),
+			Err(err) => Err(err),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testing::FakeSysfs;
+
+	#[test]
+	fn read_lux_applies_offset_and_scale() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let als = sysfs.add_light_sensor(0, 20, 0.5, 4.0).unwrap();
+		let sensor = LightSensor::find_in(sysfs.iio_root()).unwrap();
+		assert_eq!(sensor.read_lux().unwrap(), 12.0);
+
+		als.write("in_illuminance_input", 300.5);
+		assert_eq!(sensor.read_lux().unwrap(), 300.5);
+	}
+
+	#[test]
+	fn find_in_reports_a_missing_sensor() {
+		let sysfs = FakeSysfs::new().unwrap();
+		sysfs.add_light_sensor(0, 1, 1.0, 0.0).unwrap().remove("in_illuminance_raw");
+		match LightSensor::find_in(sysfs.iio_root()) {
+			Err(Error::DeviceNotFound(_)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+}
@@ -1,17 +1,88 @@
 //! This is a Rust library for controlling the backlight on Linux systems via
-//! the /sys/class/backlight interface.
+//! the /sys/class/backlight interface, as well as keyboard backlights and
+//! other LEDs via /sys/class/leds.
 //!
 //! [`backlight`]: https://github.com/andy-sdc/backlight.git
 //!
 //! This crate allows you to:
 //! - Get the maximum brightness supported by the backlight. See: [`get_max_brightness()`].
 //! - Get the current brightness level. See: [`get_brightness()`].
+//! - Get the brightness level reported by the hardware. See: [`get_actual_brightness()`].
+//! - Get notified when the brightness is changed elsewhere. See: [`watch()`].
 //! - Get the current brightness level as a percentage of the maximum. See: [`get_percent()`].
 //! - Set a new brightness level. See: [`set_brightness()`].
 //! - Set a new brightness level as a percentage of the maximum. See: [`set_percent()`].
+//! - Work in perceived brightness on linear panels. See: [`set_perceptual()`].
+//! - Use a custom response curve for percentages. See: [`set_curve()`].
+//! - Step the brightness up or down. See: [`adjust_percent()`] and [`adjust_brightness()`].
+//! - Keep the brightness within safe limits. See: [`set_limits()`].
+//! - Fade smoothly between levels. See: [`fade_to()`] and [`fade_to_percent()`].
+//! - Drive backlights wired directly to a PWM channel. See: [`Pwm`].
+//! - Plug in other hardware, or a mock, behind the same interface. See: [`BacklightBackend`].
+//! - Change the brightness as an unprivileged user through systemd-logind. See:
+//!   [`Logind`], enabled with the `logind` cargo feature.
+//! - Follow the ambient light reported by an IIO sensor. See: [`AutoBrightness`].
+//! - Learn the user's preferred brightness for each light level. See: [`Preferences`].
+//! - Turn the backlight off and on again. See: [`power_off()`] and [`power_on()`].
+//! - Save the brightness at shutdown and restore it at boot. See: [`StateStore`] and the `backlight-state` binary.
+//! - Discover the backlight devices present on the system. See: [`list()`].
+//! - Control keyboard backlights and other LEDs. See: [`led()`] and [`find_leds()`].
+//! - Set the colour of multicolor LEDs. See: [`get_channels()`] and [`set_color()`].
+//! - Attach LEDs to kernel triggers such as `timer`. See: [`set_trigger()`] and [`set_timer()`].
+//! - Pick the preferred device when there are several. See: [`default_device()`].
+//! - Generate udev rules for unprivileged access, and find out why a write was
+//!   refused. See: the `permissions` module.
+//! - Control the backlight from the shell with the `backlight` binary, built
+//!   with the `cli` cargo feature.
+//! - Run against a fake sysfs tree for testing. See: [`with_root()`] and the
+//!   `testing` module, enabled with the `testing` cargo feature.
+//!
+//! Every fallible operation returns an [`Error`] describing what went wrong,
+//! e.g. a missing device, a permission problem or a malformed attribute.
 //!
 //! ## Usage examples (see also examples folder)
 //!
+//! ### List the available backlight devices
+//!
+//! ```no_run
+//! extern crate backlight;
+//! use backlight::Brightness;
+//!
+//! fn main() {
+//!     for device in Brightness::list().unwrap() {
+//!         println!("{} ({}): max {}", device.name(), device.kind(), device.max_brightness());
+//!     }
+//! }
+//! ```
+//!
+//! ### Open the preferred backlight device
+//!
+//! ```no_run
+//! extern crate backlight;
+//! use backlight::Brightness;
+//!
+//! fn main() {
+//!     let selection = Brightness::select_default().unwrap();
+//!     println!("Using {}: {}", selection.device().name(), selection);
+//!
+//!     let br = selection.device().open();
+//!     println!("Current brightness: {}", br.get_brightness().unwrap());
+//! }
+//! ```
+//!
+//! ### Turn on the keyboard backlight
+//!
+//! ```no_run
+//! extern crate backlight;
+//! use backlight::{Brightness, LedFunction};
+//!
+//! fn main() {
+//!     for led in Brightness::find_leds(&LedFunction::KbdBacklight).unwrap() {
+//!         led.open().set_percent(100).unwrap();
+//!     }
+//! }
+//! ```
+//!
 //! ### Get the maximum allowable brightness level
 //!
 //! ```no_run
@@ -54,6 +125,35 @@
 //! } 
 //! ```
 //!
+//! ### Use a gamma curve for percentages
+//!
+//! ```no_run
+//! extern crate backlight;
+//! use backlight::Brightness;
+//! use backlight::curve::Gamma;
+//!
+//! fn main() {
+//!     let mut br = Brightness::new("backlight-lcd");
+//!     br.set_curve(Gamma::new(2.2).unwrap());
+//!
+//!     br.set_percent(10).unwrap();
+//!     assert_eq!(br.get_percent().unwrap(), 10);
+//! }
+//! ```
+//!
+//! ### Fade to full brightness over half a second
+//!
+//! ```no_run
+//! extern crate backlight;
+//! use std::time::Duration;
+//! use backlight::{Brightness, Easing};
+//!
+//! fn main() {
+//!     let br = Brightness::new("backlight-lcd");
+//!     br.fade_to_percent(100, Duration::from_millis(500), Easing::EaseInOut).unwrap();
+//! }
+//! ```
+//!
 //! ### Set a new brightness level
 //!
 //! ```no_run
@@ -104,97 +204,981 @@
 //! ```
 //!
 
-use std::fs::{File, OpenOptions};
-use std::io::Read;
-use std::io;
-use std::io::Write;
-use std::path::{PathBuf};
+extern crate libc;
+#[cfg(feature = "logind")]
+extern crate zbus;
+
+pub mod auto;
+pub mod backend;
+pub mod curve;
+mod device;
+mod error;
+pub mod fade;
+pub mod iio;
+mod limits;
+#[cfg(feature = "logind")]
+pub mod logind;
+mod multicolor;
+pub mod permissions;
+mod power;
+pub mod pwm;
+pub mod scale;
+mod state;
+mod sysfs;
+mod trigger;
+pub mod watch;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+use std::path::Path;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
 
-pub struct Brightness {
-	backend: String,
-	max_brightness: i32,
+use fade::{Clock, SystemClock};
+
+pub use auto::{AutoBrightness, LuxCurve, Preferences};
+pub use backend::{BacklightBackend, Sysfs};
+pub use device::{BacklightType, Device, DeviceClass, LedFunction, Selection, SYSFS_BACKLIGHT, SYSFS_LEDS};
+pub use curve::Curve;
+pub use error::Error;
+pub use fade::{CancelToken, Easing, Fade, FadeOutcome};
+pub use iio::LightSensor;
+pub use limits::Limits;
+#[cfg(feature = "logind")]
+pub use logind::Logind;
+pub use multicolor::Channel;
+pub use permissions::Diagnosis;
+pub use power::BlankState;
+pub use pwm::Pwm;
+pub use scale::Scale;
+pub use state::{StateStore, STATE_DIR};
+pub use trigger::Triggers;
+pub use watch::{Change, Watcher};
+
+/// A backlight, LED or other dimmable device.  The hardware is reached
+/// through a [`BacklightBackend`], which defaults to a sysfs device
+/// directory; the constructors such as [`new()`] and [`led()`] create
+/// such sysfs backed instances, while [`from_backend()`] accepts any
+/// backend.
+///
+/// [`new()`]: #method.new
+/// [`led()`]: #method.led
+/// [`from_backend()`]: #method.from_backend
+pub struct Brightness<B: BacklightBackend = Sysfs> {
+	backend: B,
+	max_brightness: AtomicI32,
+	verify: bool,
+	curve: Box<dyn Curve + Send + Sync>,
+	saved_brightness: Mutex<Option<i32>>,
+	limits: Limits,
 }
 
-impl Brightness {
-	/// Create a new instance of the backlight device.
+impl Brightness<Sysfs> {
+	/// Create a new instance of the backlight device.  Nothing is read
+	/// until the first call that needs the device.
 	pub fn new(backend_dev: &str) -> Self {
-		let backend_path = format!("/sys/class/backlight/{}", backend_dev.to_string());
+		Brightness::with_root(SYSFS_BACKLIGHT, backend_dev)
+	}
+
+	/// Open the backlight device, reading and caching its maximum
+	/// brightness so that a missing or broken device is reported here.
+	pub fn open(backend_dev: &str) -> Result<Self, Error> {
+		Brightness::open_in(SYSFS_BACKLIGHT, backend_dev)
+	}
+
+	/// Open the backlight device that lives in `root`.  See [`open()`].
+	///
+	/// [`open()`]: #method.open
+	pub fn open_in<P: AsRef<Path>>(root: P, backend_dev: &str) -> Result<Self, Error> {
+		let br = Brightness::with_root(root, backend_dev);
+		br.refresh()?;
+		Ok(br)
+	}
+
+	/// Create a new instance of an LED device in /sys/class/leds, such as
+	/// `tpacpi::kbd_backlight`.  LEDs share the `brightness` and
+	/// `max_brightness` attributes with backlights, so everything but
+	/// [`get_actual_brightness()`] and power control works the same way;
+	/// those return [`Error::Unsupported`].
+	///
+	/// [`get_actual_brightness()`]: #method.get_actual_brightness
+	pub fn led(led_dev: &str) -> Self {
+		Brightness::with_root(SYSFS_LEDS, led_dev)
+	}
+
+	/// Create a new instance of a backlight device that lives in `root`
+	/// rather than /sys/class/backlight.  This allows the crate to be used
+	/// against a fake sysfs tree in tests.
+	pub fn with_root<P: AsRef<Path>>(root: P, backend_dev: &str) -> Self {
+		Brightness::from_path(&root.as_ref().join(backend_dev))
+	}
+
+	/// Create an instance for the device in the given sysfs directory.
+	pub(crate) fn from_path(path: &Path) -> Self {
+		Brightness::from_backend(Sysfs::new(path))
+	}
+
+	/// Create an instance for a device whose maximum brightness is known.
+	pub(crate) fn from_device(path: &Path, max_brightness: i32) -> Self {
+		let br = Brightness::from_path(path);
+		br.max_brightness.store(max_brightness, Ordering::Relaxed);
+		br
+	}
+
+	/// Return a description of every backlight device found in
+	/// /sys/class/backlight, sorted by name.
+	pub fn list() -> Result<Vec<Device>, Error> {
+		Brightness::list_in(SYSFS_BACKLIGHT)
+	}
+
+	/// Return a description of every backlight device found in `root`,
+	/// sorted by name.
+	pub fn list_in<P: AsRef<Path>>(root: P) -> Result<Vec<Device>, Error> {
+		device::scan(root.as_ref(), DeviceClass::Backlight)
+	}
+
+	/// Return a description of every LED device found in /sys/class/leds,
+	/// sorted by name.
+	pub fn list_leds() -> Result<Vec<Device>, Error> {
+		Brightness::list_leds_in(SYSFS_LEDS)
+	}
+
+	/// Return a description of every LED device found in `root`, sorted
+	/// by name.
+	pub fn list_leds_in<P: AsRef<Path>>(root: P) -> Result<Vec<Device>, Error> {
+		device::scan(root.as_ref(), DeviceClass::Led)
+	}
+
+	/// Return the LED devices in /sys/class/leds that have the given
+	/// function, e.g. [`LedFunction::KbdBacklight`].
+	pub fn find_leds(function: &LedFunction) -> Result<Vec<Device>, Error> {
+		Brightness::find_leds_in(SYSFS_LEDS, function)
+	}
+
+	/// Return the LED devices in `root` that have the given function.
+	pub fn find_leds_in<P: AsRef<Path>>(root: P, function: &LedFunction) -> Result<Vec<Device>, Error> {
+		let mut leds = Brightness::list_leds_in(root)?;
+		leds.retain(|led| led.led_function().as_ref() == Some(function));
+		Ok(leds)
+	}
+
+	/// Choose the preferred backlight device, ranking firmware interfaces
+	/// over platform interfaces over raw ones.  The returned [`Selection`]
+	/// explains why the device was chosen.
+	pub fn select_default() -> Result<Selection, Error> {
+		Brightness::select_default_in(SYSFS_BACKLIGHT)
+	}
+
+	/// Choose the preferred backlight device found in `root`.
+	pub fn select_default_in<P: AsRef<Path>>(root: P) -> Result<Selection, Error> {
+		let root = root.as_ref();
+		match Selection::choose(Brightness::list_in(root)?) {
+			Some(selection) => Ok(selection),
+			None => Err(Error::DeviceNotFound(root.to_path_buf())),
+		}
+	}
+
+	/// Open the preferred backlight device.  See [`select_default()`].
+	///
+	/// [`select_default()`]: #method.select_default
+	pub fn default_device() -> Result<Self, Error> {
+		Brightness::default_device_in(SYSFS_BACKLIGHT)
+	}
+
+	/// Open the preferred backlight device found in `root`.
+	pub fn default_device_in<P: AsRef<Path>>(root: P) -> Result<Self, Error> {
+		Ok(Brightness::select_default_in(root)?.device().open())
+	}
+}
+
+impl<B: BacklightBackend> Brightness<B> {
+	/// Create a new instance that controls the device through `backend`.
+	pub fn from_backend(backend: B) -> Self {
 		Brightness {
-			backend: backend_path,
-			max_brightness: 0,
+			backend,
+			max_brightness: AtomicI32::new(0),
+			verify: false,
+			curve: Box::new(curve::Linear),
+			saved_brightness: Mutex::new(None),
+			limits: Limits::new(),
 		}
 	}
 
+	/// Return the backend used to reach the device.
+	pub fn backend(&self) -> &B {
+		&self.backend
+	}
+
 	/// Return the maximum brightness supported back the backlight.  Read
 	/// it from the file system if it hasn't been got before.
-	pub fn get_max_brightness(&self) -> Result<i32, io::Error> {
-		if self.max_brightness > 0 {
-			return Ok(self.max_brightness);
+	pub fn get_max_brightness(&self) -> Result<i32, Error> {
+		let max = self.max_brightness.load(Ordering::Relaxed);
+		if max > 0 {
+			return Ok(max);
 		}
-		return self.get("max_brightness");
+		self.refresh()
+	}
+
+	/// Re-read the maximum brightness from the device, e.g. after the
+	/// device has been hotplugged or its driver reloaded.  A maximum that
+	/// is not positive is reported as [`Error::OutOfRange`], whatever the
+	/// backend.
+	pub fn refresh(&self) -> Result<i32, Error> {
+		let max = self.backend.read_max()?;
+		if max <= 0 {
+			return Err(Error::OutOfRange { value: max, min: 1, max: i32::MAX });
+		}
+		self.max_brightness.store(max, Ordering::Relaxed);
+		Ok(max)
 	}
 
 	/// Return the current backlight brightness setting.
-	pub fn get_brightness(&self) -> Result<i32, io::Error> {
-		return self.get("brightness");
+	pub fn get_brightness(&self) -> Result<i32, Error> {
+		self.backend.read_level()
+	}
+
+	/// Return the brightness reported by the hardware.  This can differ from
+	/// [`get_brightness()`] when the firmware overrides the requested level
+	/// or hotkeys change it behind the kernel's back.
+	///
+	/// [`get_brightness()`]: #method.get_brightness
+	pub fn get_actual_brightness(&self) -> Result<i32, Error> {
+		self.backend.read_actual_level()
+	}
+
+	/// When enabled, [`set_brightness()`] reads `actual_brightness` back
+	/// after every write and returns [`Error::Mismatch`] if the hardware
+	/// did not take the requested value.
+	///
+	/// [`set_brightness()`]: #method.set_brightness
+	pub fn verify_writes(&mut self, verify: bool) {
+		self.verify = verify;
+	}
+
+	/// Use `curve` to convert between the percentages used by
+	/// [`get_percent()`] and [`set_percent()`] and raw brightness values.
+	/// The default is [`curve::Linear`].
+	///
+	/// [`get_percent()`]: #method.get_percent
+	/// [`set_percent()`]: #method.set_percent
+	pub fn set_curve<C: Curve + Send + Sync + 'static>(&mut self, curve: C) {
+		self.curve = Box::new(curve);
+	}
+
+	/// Return the curve used to convert percentages.
+	pub fn curve(&self) -> &dyn Curve {
+		&*self.curve
 	}
 
 	/// Return the current backlight brightness as a percentage
-	/// of the maximum level.
-	pub fn get_percent(&self) -> Result<i32, io::Error> {
-		let value = try!(self.get_brightness()) as f32;
-		let max = try!(self.get_max_brightness()) as f32;
-		let result = (100 as f32) * value / max;
-		return Ok(result as i32);
+	/// of the maximum level, converted through the current curve.
+	///
+	/// Devices with fewer levels than percentages cannot represent every
+	/// percentage, so after [`set_percent()`] this returns the percentage
+	/// of the level that was actually written, e.g. 0 after setting 4% on
+	/// a device with 7 levels.
+	///
+	/// [`set_percent()`]: #method.set_percent
+	pub fn get_percent(&self) -> Result<i32, Error> {
+		let value = self.get_brightness()?;
+		let max = self.get_max_brightness()?;
+		let percent = self.curve.to_percent(f64::from(value) / f64::from(max));
+		Ok(percent.round().clamp(0.0, 100.0) as i32)
 	}
 
-	/// Set a new brightness level by writing to the file within
-	/// the /sys/class/backlight/... structure
-	pub fn set_brightness(&self, mut value: i32) -> Result<bool, io::Error> {
-		let max = try!(self.get_max_brightness());
-		if value > max {
-			value = max;
-		} else if value < 0 {
-			value = 0;
-		}
+	/// Restrict every change of brightness made through this instance to
+	/// `limits`.  The current brightness is left alone until it is next
+	/// changed.
+	pub fn set_limits(&mut self, limits: Limits) {
+		self.limits = limits;
+	}
 
-		let mut path_buffer = PathBuf::from(self.backend.clone());
-		path_buffer.push("brightness");
+	/// Return the limits applied to changes of brightness.
+	pub fn limits(&self) -> Limits {
+		self.limits
+	}
 
-		let path = path_buffer.as_path();
-		let mut file = try!(OpenOptions::new().write(true).open(path));
+	/// Set a new brightness level by writing it to the device, e.g. to
+	/// the file within the /sys/class/backlight/... structure.  Values
+	/// outside the range supported by the device, or allowed by the
+	/// [`Limits`], are clamped to it.
+	pub fn set_brightness(&self, value: i32) -> Result<bool, Error> {
+		let (min, max) = self.allowed_range(self.get_max_brightness()?);
+		let value = value.clamp(min, max);
 
-		match file.write_all(value.to_string().as_bytes()) {
-			Ok(_) => Ok(true),
-			Err(err) => Err(err)
+		self.backend.write_level(value)?;
+		if self.verify {
+			let actual = self.get_actual_brightness()?;
+			if actual != value {
+				return Err(Error::Mismatch { requested: value, actual });
+			}
 		}
+		Ok(true)
 	}
 	
 	/// Set a new backlight brightness level as a percentage of the maximum.
-	pub fn set_percent(&self, value: i32) -> Result<bool, io::Error> {
-		let max = try!(self.get_max_brightness());
-		let value = (value as f32) / (100_f32) * (max as f32) + 0.5_f32;
-		let value = value as i32;
-		return self.set_brightness(value as i32);
+	/// Returns [`Error::OutOfRange`] unless `value` is between 0 and 100;
+	/// within that, the value is clamped to the [`Limits`].  The
+	/// percentage is converted through the current curve and rounded to
+	/// the nearest level the device supports.
+	pub fn set_percent(&self, value: i32) -> Result<bool, Error> {
+		if !(0..=100).contains(&value) {
+			return Err(Error::OutOfRange { value, min: 0, max: 100 });
+		}
+		let value = value.clamp(self.limits.get_min_percent(), self.limits.get_max_percent());
+		let raw = self.percent_to_raw(f64::from(value), self.get_max_brightness()?);
+		self.set_brightness(raw)
+	}
+
+	/// Change the brightness by `delta` raw levels, e.g. -10 for ten steps
+	/// down, and return the new level.  Stepping down stops at the minimum
+	/// of the [`Limits`], so that repeatedly pressing a brightness key
+	/// never blacks out the screen; a level that is already below the
+	/// minimum is not raised by stepping down.
+	pub fn adjust_brightness(&self, delta: i32) -> Result<i32, Error> {
+		let max = self.get_max_brightness()?;
+		let current = self.get_brightness()?;
+		let target = self.step_target(current, current.saturating_add(delta), max);
+		if target != current {
+			self.set_brightness(target)?;
+		}
+		Ok(target)
+	}
+
+	/// Change the brightness by `delta` percent, e.g. 5 for 5% brighter,
+	/// and return the new percentage.  The [`Limits`] apply as they do for
+	/// [`adjust_brightness()`].  On devices with only a few levels
+	/// a non-zero step always moves by at least one level, unless the
+	/// brightness is already at the end of its range.
+	///
+	/// [`adjust_brightness()`]: #method.adjust_brightness
+	pub fn adjust_percent(&self, delta: i32) -> Result<i32, Error> {
+		let max = self.get_max_brightness()?;
+		let current = self.get_brightness()?;
+		// Only the upper bound applies here: step_target() enforces the lower
+		// one without raising a level that is already below it.
+		let percent = self.get_percent()?.saturating_add(delta).clamp(0, self.limits.get_max_percent());
+
+		let mut target = self.percent_to_raw(f64::from(percent), max);
+		if target == current && delta != 0 {
+			target = current + delta.signum();
+		}
+		let target = self.step_target(current, target, max);
+		if target != current {
+			self.set_brightness(target)?;
+		}
+		self.get_percent()
+	}
+
+	/// Limit the destination of a relative step from `current` to the
+	/// allowed range, without raising a level that is below it when
+	/// stepping down.
+	fn step_target(&self, current: i32, target: i32, max: i32) -> i32 {
+		let (min, max) = self.allowed_range(max);
+		if target < current {
+			target.clamp(min.min(current), max)
+		} else {
+			target.clamp(min, max)
+		}
+	}
+
+	/// The level saved by [`power_off()`](#method.power_off).
+	fn saved_brightness(&self) -> MutexGuard<'_, Option<i32>> {
+		match self.saved_brightness.lock() {
+			Ok(saved) => saved,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	/// Return the lowest and highest raw levels allowed by the device and
+	/// the limits.
+	fn allowed_range(&self, max: i32) -> (i32, i32) {
+		let low = self.percent_to_raw(f64::from(self.limits.get_min_percent()), max);
+		let low = low.max(self.limits.get_min_raw()).min(max);
+		let high = self.percent_to_raw(f64::from(self.limits.get_max_percent()), max);
+		(low, high.max(low))
+	}
+
+	/// Convert a percentage into a raw level through the current curve.
+	fn percent_to_raw(&self, percent: f64, max: i32) -> i32 {
+		let raw = (self.curve.to_fraction(percent) * f64::from(max)).round() as i32;
+		raw.clamp(0, max)
 	}
 	
-	/// Read the file within the /sys/class/backlight/... structure to
-	/// get the corresponding value.
-	fn get(&self, filename: &str) -> Result<i32, io::Error> {
-		let mut path_buffer = PathBuf::from(self.backend.clone());
-		path_buffer.push(filename);
-
-		let path = path_buffer.as_path();
-		let mut file = try!(File::open(path));
-
-		let mut content = String::new();
-		try!(file.read_to_string(&mut content));
-
-		match content.trim().parse::<i32>() {
-			Ok(value) => Ok(value),
-			Err(_) => {
-				Ok(-1)
+	/// Fade from the current brightness to `target` over `duration`,
+	/// blocking until the fade is complete.
+	pub fn fade_to(&self, target: i32, duration: Duration, easing: Easing) -> Result<FadeOutcome, Error> {
+		self.fade(target, &Fade::new(duration).easing(easing), &SystemClock::new())
+	}
+
+	/// Fade from the current brightness to the raw level `target`, with the
+	/// step rate and cancellation given by `fade`, timed by `clock`.  A new
+	/// value is only written when the device's granularity allows it to
+	/// differ from the previous one.
+	pub fn fade<C: Clock>(&self, target: i32, fade: &Fade, clock: &C) -> Result<FadeOutcome, Error> {
+		let (min, max) = self.allowed_range(self.get_max_brightness()?);
+		let target = target.clamp(min, max);
+		let start = f64::from(self.get_brightness()?);
+		self.run_fade(start, f64::from(target), fade, clock, |level| level.round() as i32)
+	}
+
+	/// Fade from the current percentage to `target` over `duration`,
+	/// blocking until the fade is complete.
+	pub fn fade_to_percent(&self, target: i32, duration: Duration, easing: Easing) -> Result<FadeOutcome, Error> {
+		self.fade_percent(target, &Fade::new(duration).easing(easing), &SystemClock::new())
+	}
+
+	/// Fade from the current percentage to `target`, with the step rate and
+	/// cancellation given by `fade`, timed by `clock`.  The fade moves
+	/// evenly through percentages, so it follows the current curve.
+	pub fn fade_percent<C: Clock>(&self, target: i32, fade: &Fade, clock: &C) -> Result<FadeOutcome, Error> {
+		if !(0..=100).contains(&target) {
+			return Err(Error::OutOfRange { value: target, min: 0, max: 100 });
+		}
+		let target = target.clamp(self.limits.get_min_percent(), self.limits.get_max_percent());
+		let max = self.get_max_brightness()?;
+		let start = f64::from(self.get_percent()?);
+		self.run_fade(start, f64::from(target), fade, clock, |percent| self.percent_to_raw(percent, max))
+	}
+
+	/// Step from `start` to `end`, converting each position to a raw level
+	/// with `to_raw` and writing it whenever the level changes.
+	fn run_fade<C, R>(&self, start: f64, end: f64, fade: &Fade, clock: &C, to_raw: R) -> Result<FadeOutcome, Error>
+		where C: Clock, R: Fn(f64) -> i32
+	{
+		let (min, max) = self.allowed_range(self.get_max_brightness()?);
+		let began = clock.now();
+		let mut current = self.get_brightness()?;
+		loop {
+			if fade.is_cancelled() {
+				return Ok(FadeOutcome::Cancelled(current));
 			}
+			let elapsed = clock.now().checked_sub(began).unwrap_or_default();
+			if elapsed >= fade.duration {
+				break;
+			}
+
+			let progress = fade.easing.apply(elapsed.as_secs_f64() / fade.duration.as_secs_f64());
+			let raw = to_raw(start + (end - start) * progress).clamp(min, max);
+			if raw != current {
+				self.set_brightness(raw)?;
+				current = raw;
+			}
+			clock.sleep(fade.interval().min(fade.duration - elapsed));
+		}
+
+		if fade.is_cancelled() {
+			return Ok(FadeOutcome::Cancelled(current));
+		}
+		let target = to_raw(end).clamp(min, max);
+		if target != current {
+			self.set_brightness(target)?;
+		}
+		Ok(FadeOutcome::Completed)
+	}
+
+	/// Return the blanking state of the backlight, e.g. from `bl_power`.
+	pub fn get_power(&self) -> Result<BlankState, Error> {
+		self.backend.read_power()
+	}
+
+	/// Write a new blanking state, e.g. to `bl_power`.
+	pub fn set_power(&self, state: BlankState) -> Result<(), Error> {
+		self.backend.write_power(state)
+	}
+
+	/// Return true unless the backlight has been blanked.
+	pub fn is_powered(&self) -> Result<bool, Error> {
+		Ok(self.get_power()? == BlankState::Unblank)
+	}
+
+	/// Turn the backlight off, remembering the current brightness so that
+	/// [`power_on()`] can restore it.
+	///
+	/// [`power_on()`]: #method.power_on
+	pub fn power_off(&self) -> Result<(), Error> {
+		*self.saved_brightness() = Some(self.get_brightness()?);
+		self.set_power(BlankState::Powerdown)
+	}
+
+	/// Turn the backlight back on.  If the brightness changed while it was
+	/// off, e.g. because the driver reset it, the level saved by
+	/// [`power_off()`] is written back.
+	///
+	/// [`power_off()`]: #method.power_off
+	pub fn power_on(&self) -> Result<(), Error> {
+		self.set_power(BlankState::Unblank)?;
+		let saved = self.saved_brightness().take();
+		if let Some(saved) = saved {
+			if self.get_brightness()? != saved {
+				self.set_brightness(saved)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Brightness<Sysfs> {
+	/// Start watching for changes of brightness made by anyone, including
+	/// other processes and the firmware.
+	pub fn watch(&self) -> Result<Watcher, Error> {
+		Watcher::new(self.backend.path())
+	}
+
+	/// Return how raw brightness values relate to light output, as
+	/// reported by the `scale` attribute.  Kernels that predate the
+	/// attribute report [`Scale::Unknown`].
+	pub fn get_scale(&self) -> Result<Scale, Error> {
+		match self.read("scale") {
+			Ok(value) => Ok(Scale::parse(&value)),
+			Err(Error::DeviceNotFound(_)) => Ok(Scale::Unknown),
+			Err(err) => Err(err),
+		}
+	}
+
+	/// When enabled, [`get_percent()`] and [`set_percent()`] treat the
+	/// percentage as perceived lightness ([`curve::Cie1931`]) rather than
+	/// light output, so that 50% looks half as bright as 100%.  This only
+	/// takes effect on devices whose `scale` is linear; devices that already
+	/// apply a perceptual curve are left as they are.  Either way this
+	/// replaces the curve set by [`set_curve()`].
+	///
+	/// [`get_percent()`]: #method.get_percent
+	/// [`set_percent()`]: #method.set_percent
+	/// [`set_curve()`]: #method.set_curve
+	pub fn set_perceptual(&mut self, enabled: bool) -> Result<(), Error> {
+		if enabled && self.get_scale()? == Scale::Linear {
+			self.set_curve(curve::Cie1931);
+		} else {
+			self.set_curve(curve::Linear);
+		}
+		Ok(())
+	}
+
+	/// Return the triggers an LED supports and the active one.
+	pub fn get_triggers(&self) -> Result<Triggers, Error> {
+		Ok(Triggers::parse(&self.read("trigger")?))
+	}
+
+	/// Attach an LED to a trigger, e.g. `none`, `timer`, `heartbeat` or
+	/// `default-on`.  The kernel rejects names it does not know.
+	pub fn set_trigger(&self, trigger: &str) -> Result<(), Error> {
+		self.set("trigger", trigger)
+	}
+
+	/// Make an LED blink using the `timer` trigger, staying on for
+	/// `delay_on` and off for `delay_off` milliseconds.
+	pub fn set_timer(&self, delay_on: u32, delay_off: u32) -> Result<(), Error> {
+		self.set_trigger("timer")?;
+		self.set("delay_on", &delay_on.to_string())?;
+		self.set("delay_off", &delay_off.to_string())
+	}
+
+	/// Return the `delay_on` and `delay_off` periods of an LED attached
+	/// to the `timer` trigger, in milliseconds.
+	pub fn get_timer(&self) -> Result<(u32, u32), Error> {
+		Ok((self.get_delay("delay_on")?, self.get_delay("delay_off")?))
+	}
+
+	/// Read a delay of the `timer` trigger, which is never negative.
+	fn get_delay(&self, filename: &str) -> Result<u32, Error> {
+		let content = self.read(filename)?;
+		match content.parse() {
+			Ok(delay) => Ok(delay),
+			Err(_) => Err(Error::MalformedAttribute {
+				path: self.backend.path().join(filename),
+				content,
+			}),
+		}
+	}
+
+	/// Return the colour channels of a multicolor LED, in the order the
+	/// kernel expects intensities, along with their current intensities.
+	pub fn get_channels(&self) -> Result<Vec<Channel>, Error> {
+		let names = self.read("multi_index")?;
+		let content = self.read("multi_intensity")?;
+		let intensities = self.parse_intensities(&content)?;
+		let names: Vec<&str> = names.split_whitespace().collect();
+		if names.len() != intensities.len() {
+			return Err(Error::MalformedAttribute {
+				path: self.backend.path().join("multi_intensity"),
+				content,
+			});
+		}
+		Ok(names.iter().zip(intensities).map(|(name, intensity)| Channel::new(name, intensity)).collect())
+	}
+
+	/// Return the intensity of each channel of a multicolor LED.
+	pub fn get_intensities(&self) -> Result<Vec<i32>, Error> {
+		self.parse_intensities(&self.read("multi_intensity")?)
+	}
+
+	/// Parse the content of a `multi_intensity` attribute.
+	fn parse_intensities(&self, content: &str) -> Result<Vec<i32>, Error> {
+		let mut intensities = Vec::new();
+		for word in content.split_whitespace() {
+			match word.parse::<i32>() {
+				Ok(value) => intensities.push(value),
+				Err(_) => return Err(Error::MalformedAttribute {
+					path: self.backend.path().join("multi_intensity"),
+					content: content.to_string(),
+				}),
+			}
+		}
+		Ok(intensities)
+	}
+
+	/// Set the intensity of every channel of a multicolor LED at once.
+	/// One value is needed per channel, each between 0 and the maximum
+	/// brightness; otherwise [`Error::OutOfRange`] is returned and
+	/// nothing is written.
+	pub fn set_intensities(&self, intensities: &[i32]) -> Result<(), Error> {
+		let channels = self.read("multi_index")?.split_whitespace().count() as i32;
+		if intensities.len() as i32 != channels {
+			return Err(Error::OutOfRange { value: intensities.len() as i32, min: channels, max: channels });
+		}
+		let max = self.get_max_brightness()?;
+		if let Some(&value) = intensities.iter().find(|value| !(0..=max).contains(*value)) {
+			return Err(Error::OutOfRange { value, min: 0, max });
+		}
+
+		let values: Vec<String> = intensities.iter().map(|value| value.to_string()).collect();
+		self.set("multi_intensity", &values.join(" "))
+	}
+
+	/// Set the colour and overall brightness of a multicolor LED together.
+	/// The intensities are validated and written first, so that the new
+	/// brightness applies to the new colour.
+	pub fn set_color(&self, intensities: &[i32], brightness: i32) -> Result<bool, Error> {
+		self.set_intensities(intensities)?;
+		self.set_brightness(brightness)
+	}
+
+	/// Read the trimmed content of a file within the device directory.
+	fn read(&self, filename: &str) -> Result<String, Error> {
+		self.backend.read(filename)
+	}
+
+	/// Write a value to a file within the device directory.
+	fn set(&self, filename: &str, value: &str) -> Result<(), Error> {
+		self.backend.set(filename, value)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testing::{FakeSysfs, MockBackend, VirtualClock};
+
+	#[test]
+	fn non_positive_maximum_is_out_of_range_for_every_backend() {
+		let mock = MockBackend::new(10);
+		mock.set_max(0);
+		match Brightness::from_backend(mock).refresh() {
+			Err(Error::OutOfRange { value: 0, min: 1, .. }) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+
+		let sysfs = FakeSysfs::new().unwrap();
+		sysfs.add_backlight("lcd", BacklightType::Raw, 0).unwrap();
+		match Brightness::open_in(sysfs.backlight_root(), "lcd") {
+			Err(Error::OutOfRange { value: 0, min: 1, .. }) => {}
+			other => panic!("unexpected {:?}", other.map(|_| ())),
+		}
+
+		sysfs.add_pwm(0, 0, -1).unwrap();
+		match Brightness::from_backend(Pwm::with_root(sysfs.pwm_root(), 0, 0)).refresh() {
+			Err(Error::OutOfRange { value: -1, min: 1, .. }) => {}
+			other => panic!("unexpected {:?}", other),
 		}
 	}
+
+	#[test]
+	fn percent_reports_the_level_written() {
+		let mock = MockBackend::new(7);
+		let br = Brightness::from_backend(mock.clone());
+		br.set_percent(4).unwrap();
+		assert_eq!(mock.writes(), vec![0]);
+		assert_eq!(br.get_percent().unwrap(), 0);
+
+		br.set_percent(50).unwrap();
+		assert_eq!(br.get_brightness().unwrap(), 4);
+		assert_eq!(br.get_percent().unwrap(), 57);
+
+		// A new instance, e.g. another process, sees the same.
+		assert_eq!(Brightness::from_backend(mock).get_percent().unwrap(), 57);
+	}
+
+	/// Step by `delta` percent until the level stops changing, returning
+	/// every level written.
+	fn step_percent(levels: i32, start: i32, delta: i32) -> Vec<i32> {
+		let mock = MockBackend::new(levels);
+		mock.set_level(start);
+		let br = Brightness::from_backend(mock.clone());
+		for _ in 0..100 {
+			let percent = br.adjust_percent(delta).unwrap();
+			assert_eq!(percent, br.get_percent().unwrap());
+		}
+		mock.writes()
+	}
+
+	#[test]
+	fn adjust_percent_moves_at_least_one_level_on_coarse_devices() {
+		assert_eq!(step_percent(7, 7, -10), vec![6, 5, 4, 3, 2, 1, 0]);
+		assert_eq!(step_percent(7, 0, 5), vec![1, 2, 3, 4, 5, 6, 7]);
+		assert_eq!(step_percent(15, 15, -5), (0..15).rev().collect::<Vec<_>>());
+		assert_eq!(step_percent(15, 0, 5), (1..16).collect::<Vec<_>>());
+
+		let coarse = step_percent(15, 0, 20);
+		assert_eq!(coarse.last(), Some(&15));
+		assert!(coarse.windows(2).all(|pair| pair[1] > pair[0]));
+	}
+
+	#[test]
+	fn adjust_brightness_stops_at_the_ends() {
+		let mock = MockBackend::new(7);
+		let br = Brightness::from_backend(mock.clone());
+		assert_eq!(br.adjust_brightness(1).unwrap(), 7);
+		assert_eq!(br.adjust_brightness(-3).unwrap(), 4);
+		assert_eq!(br.adjust_brightness(-10).unwrap(), 0);
+		assert_eq!(br.adjust_brightness(-1).unwrap(), 0);
+		assert_eq!(mock.writes(), vec![4, 0]);
+	}
+
+	#[test]
+	fn limits_apply_to_every_change() {
+		let mock = MockBackend::new(100);
+		let mut br = Brightness::from_backend(mock.clone());
+		br.set_limits(Limits::new().min_percent(20).max_percent(80));
+		let clock = VirtualClock::new();
+		let fade = Fade::new(Duration::from_millis(100)).rate(50);
+
+		br.set_brightness(5).unwrap();
+		assert_eq!(br.get_brightness().unwrap(), 20);
+		br.set_brightness(95).unwrap();
+		assert_eq!(br.get_brightness().unwrap(), 80);
+		br.set_percent(0).unwrap();
+		assert_eq!(br.get_brightness().unwrap(), 20);
+		br.set_percent(100).unwrap();
+		assert_eq!(br.get_brightness().unwrap(), 80);
+
+		assert_eq!(br.adjust_brightness(10).unwrap(), 80);
+		assert_eq!(br.adjust_percent(10).unwrap(), 80);
+		assert_eq!(br.adjust_brightness(-100).unwrap(), 20);
+		assert_eq!(br.adjust_percent(-10).unwrap(), 20);
+
+		mock.clear_writes();
+		br.fade(100, &fade, &clock).unwrap();
+		assert_eq!(mock.writes().last(), Some(&80));
+		br.fade_percent(0, &fade, &clock).unwrap();
+		assert_eq!(mock.writes().last(), Some(&20));
+		assert!(mock.writes().iter().all(|&level| (20..=80).contains(&level)));
+	}
+
+	#[test]
+	fn stepping_down_never_raises_a_level_below_the_minimum() {
+		let mock = MockBackend::new(100);
+		mock.set_level(5);
+		let mut br = Brightness::from_backend(mock.clone());
+		br.set_limits(Limits::new().min_percent(20));
+
+		assert_eq!(br.adjust_brightness(-1).unwrap(), 5);
+		assert_eq!(br.adjust_percent(-5).unwrap(), 5);
+		assert_eq!(mock.writes(), vec![]);
+
+		// Stepping up enters the allowed range.
+		assert_eq!(br.adjust_percent(5).unwrap(), 20);
+	}
+
+	#[test]
+	fn get_timer_rejects_negative_delays() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let led = sysfs.add_led("status", 1).unwrap();
+		// The kernel creates these when the timer trigger is selected.
+		led.write("delay_on", 0);
+		led.write("delay_off", 0);
+		let br = Brightness::with_root(sysfs.leds_root(), "status");
+		br.set_timer(200, 800).unwrap();
+		assert_eq!(br.get_timer().unwrap(), (200, 800));
+
+		led.write("delay_off", -1);
+		match br.get_timer() {
+			Err(Error::MalformedAttribute { content, .. }) => assert_eq!(content, "-1"),
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+
+	#[test]
+	fn adjust_does_not_overflow() {
+		let mock = MockBackend::new(15);
+		mock.set_level(5);
+		let br = Brightness::from_backend(mock.clone());
+		assert_eq!(br.adjust_percent(i32::MAX).unwrap(), 100);
+		assert_eq!(br.adjust_percent(i32::MIN).unwrap(), 0);
+		assert_eq!(br.adjust_brightness(i32::MAX).unwrap(), 15);
+		assert_eq!(br.adjust_brightness(i32::MIN).unwrap(), 0);
+	}
+
+	#[test]
+	fn maximum_is_cached_until_refreshed() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let device = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+		let br = Brightness::open_in(sysfs.backlight_root(), "lcd").unwrap();
+		assert_eq!(br.get_max_brightness().unwrap(), 100);
+
+		device.write("max_brightness", 255);
+		assert_eq!(br.get_max_brightness().unwrap(), 100);
+		assert_eq!(br.refresh().unwrap(), 255);
+		assert_eq!(br.get_max_brightness().unwrap(), 255);
+
+		match Brightness::open_in(sysfs.backlight_root(), "missing") {
+			Err(Error::DeviceNotFound(_)) => {}
+			other => panic!("unexpected {:?}", other.map(|_| ())),
+		}
+	}
+
+	#[test]
+	fn verified_writes_report_a_mismatch() {
+		let mock = MockBackend::new(100);
+		mock.set_clamp(Some((10, 90)));
+		let mut br = Brightness::from_backend(mock.clone());
+
+		assert!(br.set_brightness(95).unwrap());
+		assert_eq!(br.get_brightness().unwrap(), 95);
+		assert_eq!(br.get_actual_brightness().unwrap(), 90);
+
+		br.verify_writes(true);
+		assert!(br.set_brightness(50).unwrap());
+		match br.set_brightness(5) {
+			Err(Error::Mismatch { requested: 5, actual: 10 }) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		assert_eq!(mock.writes(), [95, 50, 5]);
+	}
+
+	#[test]
+	fn power_on_restores_the_level_saved_by_power_off() {
+		let mock = MockBackend::new(100);
+		mock.set_level(60);
+		let br = Brightness::from_backend(mock.clone());
+
+		br.power_off().unwrap();
+		assert!(!br.is_powered().unwrap());
+		assert_eq!(br.get_power().unwrap(), BlankState::Powerdown);
+		// The driver resets the level while the backlight is off.
+		mock.set_level(0);
+		br.power_on().unwrap();
+		assert!(br.is_powered().unwrap());
+		assert_eq!(br.get_brightness().unwrap(), 60);
+		assert_eq!(mock.writes(), [60]);
+
+		// Nothing is written if the level survived.
+		br.power_off().unwrap();
+		br.power_on().unwrap();
+		assert_eq!(mock.writes(), [60]);
+	}
+
+	#[test]
+	fn power_is_written_to_bl_power() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let device = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+		let br = Brightness::open_in(sysfs.backlight_root(), "lcd").unwrap();
+		br.power_off().unwrap();
+		assert_eq!(device.read("bl_power"), "4");
+		br.power_on().unwrap();
+		assert_eq!(device.read("bl_power"), "0");
+
+		device.write("bl_power", 7);
+		match br.get_power() {
+			Err(Error::MalformedAttribute { content, .. }) => assert_eq!(content, "7"),
+			other => panic!("unexpected {:?}", other),
+		}
+		for value in 0..5 {
+			assert_eq!(BlankState::from_value(value).unwrap().value(), value);
+		}
+	}
+
+	#[test]
+	fn scale_is_read_from_the_device() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let device = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+		let br = Brightness::open_in(sysfs.backlight_root(), "lcd").unwrap();
+		assert_eq!(br.get_scale().unwrap(), Scale::Unknown);
+
+		for &(content, scale) in &[("linear", Scale::Linear), ("non-linear", Scale::NonLinear), ("logarithmic", Scale::Unknown)] {
+			device.write("scale", content);
+			assert_eq!(br.get_scale().unwrap(), scale);
+		}
+	}
+
+	#[test]
+	fn perceptual_percentages_only_apply_to_linear_devices() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let device = sysfs.add_backlight("lcd", BacklightType::Raw, 1000).unwrap();
+		let mut br = Brightness::open_in(sysfs.backlight_root(), "lcd").unwrap();
+
+		device.write("scale", "linear");
+		br.set_perceptual(true).unwrap();
+		br.set_percent(50).unwrap();
+		assert_eq!(device.read("brightness"), "184");
+		assert_eq!(br.get_percent().unwrap(), 50);
+
+		device.write("scale", "non-linear");
+		br.set_perceptual(true).unwrap();
+		br.set_percent(50).unwrap();
+		assert_eq!(device.read("brightness"), "500");
+
+		device.write("scale", "linear");
+		br.set_perceptual(false).unwrap();
+		br.set_percent(50).unwrap();
+		assert_eq!(device.read("brightness"), "500");
+	}
+
+	#[test]
+	fn intensities_are_checked_before_they_are_written() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let led = sysfs.add_multicolor_led("rgb:status", &["red", "green", "blue"], 255).unwrap();
+		let br = Brightness::with_root(sysfs.leds_root(), "rgb:status");
+
+		match br.set_intensities(&[255, 0]) {
+			Err(Error::OutOfRange { value: 2, min: 3, max: 3 }) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		match br.set_intensities(&[255, 256, 0]) {
+			Err(Error::OutOfRange { value: 256, min: 0, max: 255 }) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		match br.set_color(&[-1, 0, 0], 128) {
+			Err(Error::OutOfRange { value: -1, min: 0, max: 255 }) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+		assert_eq!(led.read("multi_intensity"), "255 255 255");
+		assert_eq!(led.read("brightness"), "0");
+
+		br.set_color(&[255, 128, 0], 128).unwrap();
+		assert_eq!(led.read("multi_intensity"), "255 128 0");
+		assert_eq!(led.read("brightness"), "128");
+		let channels: Vec<String> = br.get_channels().unwrap().iter().map(|channel| channel.to_string()).collect();
+		assert_eq!(channels, ["red=255", "green=128", "blue=0"]);
+
+		led.write("multi_intensity", "255 128");
+		match br.get_channels() {
+			Err(Error::MalformedAttribute { content, .. }) => assert_eq!(content, "255 128"),
+			other => panic!("unexpected {:?}", other),
+		}
+		led.write("multi_intensity", "255 high 0");
+		match br.get_channels() {
+			Err(Error::MalformedAttribute { content, .. }) => assert_eq!(content, "255 high 0"),
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+
+	#[test]
+	fn brightness_can_be_shared_between_threads() {
+		fn is_send_sync<T: Send + Sync>() {}
+		is_send_sync::<Brightness>();
+		is_send_sync::<Brightness<MockBackend>>();
+		is_send_sync::<Brightness<Pwm>>();
+	}
 }
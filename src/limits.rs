@@ -0,0 +1,67 @@
+//! Safe brightness limits applied to every change of brightness.
+
+/// Bounds on the brightness that [`Brightness`] will set, whether through
+/// raw levels, percentages, relative steps or fades.  A minimum keeps the
+/// screen readable so that a user can always see to recover, while a
+/// maximum below 100% can be used to save power or limit heat.
+///
+/// Percentages are converted to raw levels through the device's curve.
+/// When the bounds conflict, the lower bound wins.
+///
+/// [`Brightness`]: ../struct.Brightness.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+	pub(crate) min_raw: i32,
+	pub(crate) min_percent: i32,
+	pub(crate) max_percent: i32,
+}
+
+impl Limits {
+	/// Limits that allow the full range of the device.
+	pub fn new() -> Self {
+		Limits {
+			min_raw: 0,
+			min_percent: 0,
+			max_percent: 100,
+		}
+	}
+
+	/// Never go below the raw level `level`.
+	pub fn min_raw(mut self, level: i32) -> Self {
+		self.min_raw = level.max(0);
+		self
+	}
+
+	/// Never go below `percent` percent.
+	pub fn min_percent(mut self, percent: i32) -> Self {
+		self.min_percent = percent.clamp(0, 100);
+		self
+	}
+
+	/// Never go above `percent` percent.
+	pub fn max_percent(mut self, percent: i32) -> Self {
+		self.max_percent = percent.clamp(0, 100);
+		self
+	}
+
+	/// The lowest percentage allowed, before conversion to raw levels.
+	pub fn get_min_percent(&self) -> i32 {
+		self.min_percent
+	}
+
+	/// The highest percentage allowed, before conversion to raw levels.
+	pub fn get_max_percent(&self) -> i32 {
+		self.max_percent.max(self.min_percent)
+	}
+
+	/// The lowest raw level allowed, not counting the minimum percentage.
+	pub fn get_min_raw(&self) -> i32 {
+		self.min_raw
+	}
+}
+
+impl Default for Limits {
+	fn default() -> Self {
+		Limits::new()
+	}
+}
@@ -0,0 +1,255 @@
+//! Changing brightness through systemd-logind.
+//!
+//! Writing to the `brightness` attribute of a device normally requires root.
+//! logind lets a user in an active session change the brightness of
+//! backlight and LED devices through the `SetBrightness` method of their
+//! session, so [`Logind`] sends writes over D-Bus while still reading from
+//! sysfs, which anyone may do.  Enabled with the `logind` cargo feature.
+//!
+//! ```no_run
+//! extern crate backlight;
+//! use backlight::{Brightness, Logind};
+//!
+//! fn main() {
+//!     let selection = Brightness::select_default().unwrap();
+//!     let logind = Logind::new(selection.device()).unwrap();
+//!
+//!     let br = Brightness::from_backend(logind);
+//!     br.set_percent(40).unwrap();
+//! }
+//! ```
+
+use std::io;
+use std::path::PathBuf;
+
+use zbus::blocking::{Connection, ConnectionBuilder};
+
+use backend::{BacklightBackend, Sysfs};
+use {BlankState, Device, Error};
+
+/// The object path that logind resolves to the caller's own session.
+pub const AUTO_SESSION: &str = "/org/freedesktop/login1/session/auto";
+
+const DESTINATION: &str = "org.freedesktop.login1";
+const INTERFACE: &str = "org.freedesktop.login1.Session";
+
+/// A backlight or LED whose brightness is read from sysfs and written
+/// through logind.  Power control goes directly to sysfs, as logind does
+/// not offer it.
+#[derive(Debug, Clone)]
+pub struct Logind {
+	sysfs: Sysfs,
+	subsystem: String,
+	name: String,
+	connection: Connection,
+	session: String,
+}
+
+impl Logind {
+	/// Change the brightness of `device` through the caller's session on
+	/// the system bus.
+	pub fn new(device: &Device) -> Result<Self, Error> {
+		let connection = Connection::system().map_err(dbus_error)?;
+		Ok(Logind::with_connection(connection, AUTO_SESSION, device))
+	}
+
+	/// Change the brightness of `device` through `session` on the bus at
+	/// `address`, e.g. `unix:path=/tmp/test-bus`.
+	pub fn with_address(address: &str, session: &str, device: &Device) -> Result<Self, Error> {
+		let connection = ConnectionBuilder::address(address)
+			.and_then(|builder| builder.build())
+			.map_err(dbus_error)?;
+		Ok(Logind::with_connection(connection, session, device))
+	}
+
+	/// Change the brightness of `device` through `session` on an existing
+	/// connection.
+	pub fn with_connection(connection: Connection, session: &str, device: &Device) -> Self {
+		Logind {
+			sysfs: Sysfs::new(device.path()),
+			subsystem: device.class().to_string(),
+			name: device.name().to_string(),
+			connection,
+			session: session.to_string(),
+		}
+	}
+
+	/// The object path of the session that writes go through.
+	pub fn session(&self) -> &str {
+		&self.session
+	}
+
+	/// The sysfs device that is read from.
+	pub fn sysfs(&self) -> &Sysfs {
+		&self.sysfs
+	}
+
+	fn brightness_path(&self) -> PathBuf {
+		self.sysfs.path().join("brightness")
+	}
+}
+
+impl BacklightBackend for Logind {
+	fn read_level(&self) -> Result<i32, Error> {
+		self.sysfs.read_level()
+	}
+
+	fn read_max(&self) -> Result<i32, Error> {
+		self.sysfs.read_max()
+	}
+
+	fn write_level(&self, value: i32) -> Result<(), Error> {
+		let body = (self.subsystem.as_str(), self.name.as_str(), value.max(0) as u32);
+		match self.connection.call_method(Some(DESTINATION), self.session.as_str(), Some(INTERFACE), "SetBrightness", &body) {
+			Ok(_) => Ok(()),
+			Err(zbus::Error::MethodError(ref name, _, _)) if is_access_denied(name.as_str()) => {
+				Err(Error::PermissionDenied(self.brightness_path()))
+			}
+			Err(err) => Err(dbus_error(err)),
+		}
+	}
+
+	fn read_actual_level(&self) -> Result<i32, Error> {
+		self.sysfs.read_actual_level()
+	}
+
+	fn read_power(&self) -> Result<BlankState, Error> {
+		self.sysfs.read_power()
+	}
+
+	fn write_power(&self, state: BlankState) -> Result<(), Error> {
+		self.sysfs.write_power(state)
+	}
+}
+
+/// Return true for the errors logind gives when the caller is not allowed
+/// to change the brightness, e.g. because their session is not active.
+fn is_access_denied(name: &str) -> bool {
+	name == "org.freedesktop.DBus.Error.AccessDenied" || name == "org.freedesktop.DBus.Error.InteractiveAuthorizationRequired"
+}
+
+pub(crate) fn dbus_error(err: zbus::Error) -> Error {
+	Error::Io(io::Error::other(err))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{BufRead, BufReader};
+	use std::process::{Child, Command, Stdio};
+
+	use super::*;
+	use testing::{Failure, FakeDevice, FakeSysfs, MockLogind};
+	use {BacklightType, Brightness};
+
+	fn open(sysfs: &FakeSysfs) -> (FakeDevice, MockLogind, Brightness<Logind>) {
+		let lcd = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+		let device = &Brightness::list_in(sysfs.backlight_root()).unwrap()[0];
+		let (mock, connection) = MockLogind::new(sysfs).unwrap();
+		let br = Brightness::from_backend(Logind::with_connection(connection, AUTO_SESSION, device));
+		(lcd, mock, br)
+	}
+
+	#[test]
+	fn writes_go_through_the_session() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let (lcd, mock, br) = open(&sysfs);
+		br.set_brightness(30).unwrap();
+		br.set_percent(45).unwrap();
+		assert_eq!(lcd.read("brightness"), "45");
+		assert_eq!(mock.calls(), [("backlight".to_string(), "lcd".to_string(), 30), ("backlight".to_string(), "lcd".to_string(), 45)]);
+	}
+
+	#[test]
+	fn reads_come_from_sysfs() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let (lcd, mock, br) = open(&sysfs);
+		lcd.write("brightness", 12);
+		lcd.write("actual_brightness", 11);
+		assert_eq!(br.get_brightness().unwrap(), 12);
+		assert_eq!(br.get_actual_brightness().unwrap(), 11);
+		assert_eq!(br.get_max_brightness().unwrap(), 100);
+		br.power_off().unwrap();
+		assert_eq!(lcd.read("bl_power"), "4");
+		assert!(mock.calls().is_empty());
+	}
+
+	#[test]
+	fn access_denied_is_permission_denied() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let (lcd, mock, br) = open(&sysfs);
+		mock.set_failure(Some(Failure::PermissionDenied));
+		match br.set_brightness(30) {
+			Err(Error::PermissionDenied(path)) => assert_eq!(path, br.backend().sysfs().path().join("brightness")),
+			other => panic!("unexpected {:?}", other),
+		}
+		assert_eq!(lcd.read("brightness"), "100");
+		assert!(is_access_denied("org.freedesktop.DBus.Error.InteractiveAuthorizationRequired"));
+		assert!(!is_access_denied("org.freedesktop.DBus.Error.Failed"));
+	}
+
+	#[test]
+	fn other_failures_are_io_errors() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let (_lcd, mock, br) = open(&sysfs);
+		for &(failure, message) in &[(Failure::NotFound, "lcd"), (Failure::Rejected, "Failed to write brightness")] {
+			mock.set_failure(Some(failure));
+			match br.set_brightness(30) {
+				Err(Error::Io(err)) => {
+					assert_eq!(err.kind(), io::ErrorKind::Other);
+					assert!(err.to_string().contains(message), "{}", err);
+				}
+				other => panic!("unexpected {:?}", other),
+			}
+		}
+		assert!(mock.calls().is_empty());
+	}
+
+	/// A private session bus, stopped when dropped.
+	struct Bus {
+		daemon: Child,
+		address: String,
+	}
+
+	impl Bus {
+		fn start(sysfs: &FakeSysfs) -> Self {
+			let address = format!("unix:path={}", sysfs.path().join("bus").display());
+			let mut daemon = Command::new("dbus-daemon")
+				.args(["--session", "--nofork", "--print-address", &format!("--address={}", address)])
+				.stdout(Stdio::piped())
+				.spawn()
+				.expect("cannot start dbus-daemon");
+			// The address is printed once the bus accepts connections.
+			let mut line = String::new();
+			BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut line).unwrap();
+			Bus { daemon, address }
+		}
+	}
+
+	impl Drop for Bus {
+		fn drop(&mut self) {
+			let _ = self.daemon.kill();
+			let _ = self.daemon.wait();
+		}
+	}
+
+	#[test]
+	#[ignore = "needs dbus-daemon"]
+	fn mock_serves_logind_on_a_session_bus() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let lcd = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+		let device = &Brightness::list_in(sysfs.backlight_root()).unwrap()[0];
+		let bus = Bus::start(&sysfs);
+
+		let mock = MockLogind::on_bus(&sysfs, &bus.address).unwrap();
+		let br = Brightness::from_backend(Logind::with_address(&bus.address, AUTO_SESSION, device).unwrap());
+		br.set_percent(60).unwrap();
+		assert_eq!(lcd.read("brightness"), "60");
+		assert_eq!(mock.calls(), [("backlight".to_string(), "lcd".to_string(), 60)]);
+
+		mock.set_failure(Some(Failure::PermissionDenied));
+		match br.set_percent(10) {
+			Err(Error::PermissionDenied(_)) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+}
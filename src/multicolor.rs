@@ -0,0 +1,37 @@
+//! Channels of LEDs in the multicolor class.
+
+use std::fmt;
+
+/// One colour channel of a multicolor LED, from `multi_index` and
+/// `multi_intensity`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Channel {
+	name: String,
+	intensity: i32,
+}
+
+impl Channel {
+	pub(crate) fn new(name: &str, intensity: i32) -> Self {
+		Channel {
+			name: name.to_string(),
+			intensity,
+		}
+	}
+
+	/// The colour of the channel, e.g. `red`.
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// The intensity of the channel.  The kernel scales it by
+	/// `brightness / max_brightness` to get the channel's output.
+	pub fn intensity(&self) -> i32 {
+		self.intensity
+	}
+}
+
+impl fmt::Display for Channel {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}={}", self.name, self.intensity)
+	}
+}
@@ -0,0 +1,416 @@
+//! Granting unprivileged users write access to brightness attributes, and
+//! explaining why a write was refused.
+//!
+//! The kernel creates `brightness` and `bl_power` writable by root only.
+//! The usual fix is a udev rule that hands them to a group such as `video`
+//! whenever the device appears; [`udev_rules()`] generates one for a set of
+//! devices.  When a write still fails with [`Error::PermissionDenied`],
+//! [`diagnose()`] tells which part of that setup is missing:
+//!
+//! ```no_run
+//! extern crate backlight;
+//! use backlight::{Brightness, Error};
+//! use backlight::permissions;
+//!
+//! fn main() {
+//!     let br = Brightness::default_device().unwrap();
+//!     if let Err(Error::PermissionDenied(path)) = br.set_percent(50) {
+//!         println!("{}", permissions::diagnose(&path).unwrap());
+//!     }
+//! }
+//! ```
+//!
+//! [`udev_rules()`]: fn.udev_rules.html
+//! [`diagnose()`]: fn.diagnose.html
+//! [`Error::PermissionDenied`]: ../enum.Error.html#variant.PermissionDenied
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use libc;
+
+use {Device, DeviceClass, Error};
+
+/// Where udev rules written by the administrator live.
+pub const UDEV_RULES_PATH: &str = "/etc/udev/rules.d/90-backlight.rules";
+
+/// The group that is usually given access to backlights.
+pub const DEFAULT_GROUP: &str = "video";
+
+/// Return the content of a udev rules file that lets members of `group`
+/// change the brightness of `devices`, and the power state of the
+/// backlights among them.  Install it as [`UDEV_RULES_PATH`] and run
+/// `udevadm trigger` or reboot to apply it.
+///
+/// [`UDEV_RULES_PATH`]: constant.UDEV_RULES_PATH.html
+pub fn udev_rules(devices: &[Device], group: &str) -> String {
+	let mut rules = format!("# Let members of the {} group change the brightness of\n# backlights and LEDs.\n", group);
+	for device in devices {
+		let attributes: &[&str] = match device.class() {
+			DeviceClass::Backlight => &["brightness", "bl_power"],
+			DeviceClass::Led => &["brightness"],
+		};
+		let files: Vec<String> = attributes.iter().map(|a| format!("$sys$devpath/{}", a)).collect();
+		let files = files.join(" ");
+		rules.push_str(&format!(
+			"ACTION==\"add\", SUBSYSTEM==\"{}\", KERNEL==\"{}\", RUN+=\"/bin/chgrp {} {}\", RUN+=\"/bin/chmod g+w {}\"\n",
+			device.class(), escape(device.name()), group, files, files
+		));
+	}
+	rules
+}
+
+/// Escape the characters that udev treats as a pattern in a match.
+fn escape(name: &str) -> String {
+	let mut escaped = String::new();
+	for c in name.chars() {
+		if c == '*' || c == '?' || c == '[' || c == ']' || c == '|' || c == '\\' || c == '"' {
+			escaped.push('\\');
+		}
+		escaped.push(c);
+	}
+	escaped
+}
+
+/// The identity that access to a file is checked against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+	user: Option<String>,
+	uid: u32,
+	groups: Vec<u32>,
+	configured: Vec<u32>,
+}
+
+impl Credentials {
+	/// The identity of this process.  The groups the user has been given
+	/// in /etc/group since logging in are recorded separately, as they
+	/// only take effect in a new login session.
+	pub fn current() -> Self {
+		// These calls cannot fail, and getgroups() only writes up to the
+		// length it is given.
+		let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+		let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+		let mut groups = vec![0; count.max(0) as usize];
+		let count = unsafe { libc::getgroups(groups.len() as libc::c_int, groups.as_mut_ptr()) };
+		groups.truncate(count.max(0) as usize);
+		groups.push(gid);
+		groups.sort_unstable();
+		groups.dedup();
+
+		let user = user_name(uid);
+		let mut configured = match user {
+			Some(ref user) => configured_groups(user),
+			None => Vec::new(),
+		};
+		configured.push(gid);
+		configured.sort_unstable();
+		configured.dedup();
+		Credentials { user, uid, groups, configured }
+	}
+
+	/// An identity with user ID `uid` that is a member of `groups`.
+	pub fn new(uid: u32, groups: Vec<u32>) -> Self {
+		Credentials {
+			user: user_name(uid),
+			uid,
+			configured: groups.clone(),
+			groups,
+		}
+	}
+
+	/// Record that the user has been given `groups` in /etc/group, even
+	/// though the process only belongs to those passed to [`new()`].
+	///
+	/// [`new()`]: #method.new
+	pub fn configured_groups(mut self, groups: Vec<u32>) -> Self {
+		self.configured = groups;
+		self
+	}
+
+	/// The name of the user, or their ID if they have no name.
+	pub fn user(&self) -> String {
+		match self.user {
+			Some(ref user) => user.clone(),
+			None => self.uid.to_string(),
+		}
+	}
+
+	/// The user ID.
+	pub fn uid(&self) -> u32 {
+		self.uid
+	}
+
+	/// The groups the process belongs to.
+	pub fn groups(&self) -> &[u32] {
+		&self.groups
+	}
+}
+
+/// Why an attribute cannot be written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+	/// The attribute does not exist.
+	Missing,
+	/// The user owns the attribute, but its owner may not write to it.
+	OwnerNotWritable,
+	/// The attribute's group may not write to it, which usually means no
+	/// udev rule has granted access.
+	GroupNotWritable,
+	/// The attribute's group may write to it, but the user is not a member.
+	NotInGroup,
+	/// The user has been added to the attribute's group, but only after
+	/// logging in, so the change has not taken effect yet.
+	LoginRequired,
+}
+
+/// The result of checking whether an attribute can be written.
+#[derive(Debug, Clone)]
+pub struct Diagnosis {
+	path: PathBuf,
+	user: String,
+	owner: u32,
+	group: String,
+	mode: u32,
+	problem: Option<Problem>,
+}
+
+impl Diagnosis {
+	/// The attribute that was checked.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Return true if the attribute can be written.
+	pub fn is_writable(&self) -> bool {
+		self.problem.is_none()
+	}
+
+	/// The reason the attribute cannot be written, if any.
+	pub fn problem(&self) -> Option<&Problem> {
+		self.problem.as_ref()
+	}
+
+	/// The user ID that owns the attribute.
+	pub fn owner(&self) -> u32 {
+		self.owner
+	}
+
+	/// The name of the group that owns the attribute, or its ID if it has
+	/// no name.
+	pub fn group(&self) -> &str {
+		&self.group
+	}
+
+	/// The permission bits of the attribute, e.g. `0o664`.
+	pub fn mode(&self) -> u32 {
+		self.mode
+	}
+
+	/// A human readable explanation, with the fix when there is a problem.
+	pub fn reason(&self) -> String {
+		let path = self.path.display();
+		match self.problem {
+			None => format!("{} is writable by {}", path, self.user),
+			Some(Problem::Missing) => format!("{} does not exist", path),
+			Some(Problem::OwnerNotWritable) => format!(
+				"{} (mode {:o}) is owned by {} but not writable by its owner; run `chmod u+w` on it",
+				path, self.mode, self.user
+			),
+			Some(Problem::GroupNotWritable) => format!(
+				"{} (mode {:o}, group {}) is not writable by its group; install a udev rule \
+				 granting access to the {} group, e.g. from `backlight udev-rule`",
+				path, self.mode, self.group, DEFAULT_GROUP
+			),
+			Some(Problem::NotInGroup) => format!(
+				"{} is writable by group {}, but {} is not a member; run `usermod -aG {} {}` and log in again",
+				path, self.group, self.user, self.group, self.user
+			),
+			Some(Problem::LoginRequired) => format!(
+				"{} is writable by group {}, which {} joined after logging in; log out and back in",
+				path, self.group, self.user
+			),
+		}
+	}
+}
+
+impl fmt::Display for Diagnosis {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.reason())
+	}
+}
+
+/// Check whether this process can write the attribute at `path`, e.g. the
+/// path in an [`Error::PermissionDenied`].
+///
+/// [`Error::PermissionDenied`]: ../enum.Error.html#variant.PermissionDenied
+pub fn diagnose<P: AsRef<Path>>(path: P) -> Result<Diagnosis, Error> {
+	diagnose_as(path, &Credentials::current())
+}
+
+/// Check whether a process with `credentials` can write the attribute at
+/// `path`, e.g. to test a service account.
+pub fn diagnose_as<P: AsRef<Path>>(path: P, credentials: &Credentials) -> Result<Diagnosis, Error> {
+	let path = path.as_ref();
+	let mut diagnosis = Diagnosis {
+		path: path.to_path_buf(),
+		user: credentials.user(),
+		owner: 0,
+		group: String::new(),
+		mode: 0,
+		problem: None,
+	};
+	let metadata = match fs::metadata(path) {
+		Ok(metadata) => metadata,
+		Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+			diagnosis.problem = Some(Problem::Missing);
+			return Ok(diagnosis);
+		}
+		Err(err) => return Err(Error::from_io(err, path)),
+	};
+	let (owner, gid, mode) = (metadata.uid(), metadata.gid(), metadata.mode() & 0o7777);
+	diagnosis.owner = owner;
+	diagnosis.group = group_name(gid).unwrap_or_else(|| gid.to_string());
+	diagnosis.mode = mode;
+
+	// As in the kernel, only the first class that matches is checked:
+	// the owner's bits, else the group's, else everyone else's.
+	diagnosis.problem = if credentials.uid == 0 {
+		None
+	} else if credentials.uid == owner {
+		if mode & 0o200 != 0 { None } else { Some(Problem::OwnerNotWritable) }
+	} else if credentials.groups.contains(&gid) {
+		if mode & 0o020 != 0 { None } else { Some(Problem::GroupNotWritable) }
+	} else if mode & 0o002 != 0 {
+		None
+	} else if mode & 0o020 == 0 {
+		Some(Problem::GroupNotWritable)
+	} else if credentials.configured.contains(&gid) {
+		Some(Problem::LoginRequired)
+	} else {
+		Some(Problem::NotInGroup)
+	};
+	Ok(diagnosis)
+}
+
+/// Look up the name of a user in /etc/passwd.
+fn user_name(uid: u32) -> Option<String> {
+	let passwd = fs::read_to_string("/etc/passwd").ok()?;
+	passwd.lines()
+		.map(|line| line.split(':').collect::<Vec<_>>())
+		.find(|fields| fields.len() > 2 && fields[2].parse() == Ok(uid))
+		.map(|fields| fields[0].to_string())
+}
+
+/// Look up the name of a group in /etc/group.
+fn group_name(gid: u32) -> Option<String> {
+	let group = fs::read_to_string("/etc/group").ok()?;
+	group.lines()
+		.map(|line| line.split(':').collect::<Vec<_>>())
+		.find(|fields| fields.len() > 2 && fields[2].parse() == Ok(gid))
+		.map(|fields| fields[0].to_string())
+}
+
+/// The groups that /etc/group lists `user` as a member of.
+fn configured_groups(user: &str) -> Vec<u32> {
+	let group = match fs::read_to_string("/etc/group") {
+		Ok(group) => group,
+		Err(_) => return Vec::new(),
+	};
+	group.lines()
+		.map(|line| line.split(':').collect::<Vec<_>>())
+		.filter(|fields| fields.len() > 3 && fields[3].split(',').any(|member| member == user))
+		.filter_map(|fields| fields[2].parse().ok())
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::os::unix::fs::{chown, PermissionsExt};
+	use testing::FakeSysfs;
+	use {BacklightType, Brightness};
+
+	/// Create an attribute with `mode`, owned by a user other than root so
+	/// that the permission bits matter.  Returns its owner and group.
+	fn attribute(sysfs: &FakeSysfs, mode: u32) -> (PathBuf, u32, u32) {
+		let path = sysfs.path().join("brightness");
+		fs::write(&path, "0\n").unwrap();
+		if unsafe { libc::geteuid() } == 0 {
+			chown(&path, Some(1000), Some(1000)).unwrap();
+		}
+		fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+		let metadata = fs::metadata(&path).unwrap();
+		(path, metadata.uid(), metadata.gid())
+	}
+
+	fn problem(path: &Path, credentials: &Credentials) -> Option<Problem> {
+		diagnose_as(path, credentials).unwrap().problem().cloned()
+	}
+
+	#[test]
+	fn only_the_matching_class_is_checked() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let (path, owner, group) = attribute(&sysfs, 0o464);
+		let other = owner + 1;
+
+		// The owner may not write, even though their group may.
+		assert_eq!(problem(&path, &Credentials::new(owner, vec![group])), Some(Problem::OwnerNotWritable));
+		assert_eq!(problem(&path, &Credentials::new(other, vec![group])), None);
+		assert_eq!(problem(&path, &Credentials::new(0, vec![0])), None);
+
+		let (path, owner, group) = attribute(&sysfs, 0o642);
+		// Members of the group may not write, even though everyone else may.
+		assert_eq!(problem(&path, &Credentials::new(owner + 1, vec![group])), Some(Problem::GroupNotWritable));
+		assert_eq!(problem(&path, &Credentials::new(owner + 1, vec![group + 1])), None);
+		assert_eq!(problem(&path, &Credentials::new(owner, vec![group])), None);
+	}
+
+	#[test]
+	fn problems_explain_the_fix() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let (path, owner, group) = attribute(&sysfs, 0o644);
+		let user = Credentials::new(owner + 1, vec![group + 1]);
+		assert_eq!(problem(&path, &user), Some(Problem::GroupNotWritable));
+		assert!(diagnose_as(&path, &user).unwrap().reason().contains("udev rule"));
+
+		let (path, _, group) = attribute(&sysfs, 0o664);
+		assert_eq!(problem(&path, &user), Some(Problem::NotInGroup));
+		let joined = user.clone().configured_groups(vec![group + 1, group]);
+		assert_eq!(problem(&path, &joined), Some(Problem::LoginRequired));
+
+		let diagnosis = diagnose_as(&path, &Credentials::new(owner + 1, vec![group])).unwrap();
+		assert!(diagnosis.is_writable());
+		assert_eq!(diagnosis.mode(), 0o664);
+
+		let missing = sysfs.path().join("missing");
+		assert_eq!(problem(&missing, &user), Some(Problem::Missing));
+	}
+
+	#[test]
+	fn udev_rules_cover_backlights_and_leds() {
+		let sysfs = FakeSysfs::new().unwrap();
+		sysfs.add_backlight("acpi_video0", BacklightType::Firmware, 15).unwrap();
+		sysfs.add_led("tpacpi::kbd_backlight", 2).unwrap();
+		let mut devices = Brightness::list_in(sysfs.backlight_root()).unwrap();
+		devices.extend(Brightness::list_leds_in(sysfs.leds_root()).unwrap());
+
+		assert_eq!(udev_rules(&devices, "video"), concat!(
+			"# Let members of the video group change the brightness of\n",
+			"# backlights and LEDs.\n",
+			"ACTION==\"add\", SUBSYSTEM==\"backlight\", KERNEL==\"acpi_video0\", ",
+			"RUN+=\"/bin/chgrp video $sys$devpath/brightness $sys$devpath/bl_power\", ",
+			"RUN+=\"/bin/chmod g+w $sys$devpath/brightness $sys$devpath/bl_power\"\n",
+			"ACTION==\"add\", SUBSYSTEM==\"leds\", KERNEL==\"tpacpi::kbd_backlight\", ",
+			"RUN+=\"/bin/chgrp video $sys$devpath/brightness\", ",
+			"RUN+=\"/bin/chmod g+w $sys$devpath/brightness\"\n",
+		));
+	}
+
+	#[test]
+	fn udev_patterns_are_escaped() {
+		assert_eq!(escape("odd*name[1]"), "odd\\*name\\[1\\]");
+	}
+}
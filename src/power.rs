@@ -0,0 +1,59 @@
+//! Blanking states of the `bl_power` attribute.
+
+use std::fmt;
+
+/// The framebuffer blanking states accepted by `bl_power`.  Backlight
+/// drivers only distinguish between [`Unblank`] and everything else.
+///
+/// [`Unblank`]: #variant.Unblank
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlankState {
+	/// FB_BLANK_UNBLANK: the backlight is on.
+	Unblank,
+	/// FB_BLANK_NORMAL: the screen is blanked but the backlight may stay on.
+	Normal,
+	/// FB_BLANK_VSYNC_SUSPEND: vertical sync is suspended.
+	VsyncSuspend,
+	/// FB_BLANK_HSYNC_SUSPEND: horizontal sync is suspended.
+	HsyncSuspend,
+	/// FB_BLANK_POWERDOWN: the backlight is off.
+	Powerdown,
+}
+
+impl BlankState {
+	/// Convert the numeric value of `bl_power` into a state.
+	pub fn from_value(value: i32) -> Option<Self> {
+		match value {
+			0 => Some(BlankState::Unblank),
+			1 => Some(BlankState::Normal),
+			2 => Some(BlankState::VsyncSuspend),
+			3 => Some(BlankState::HsyncSuspend),
+			4 => Some(BlankState::Powerdown),
+			_ => None,
+		}
+	}
+
+	/// The numeric value written to `bl_power` for this state.
+	pub fn value(self) -> i32 {
+		match self {
+			BlankState::Unblank => 0,
+			BlankState::Normal => 1,
+			BlankState::VsyncSuspend => 2,
+			BlankState::HsyncSuspend => 3,
+			BlankState::Powerdown => 4,
+		}
+	}
+}
+
+impl fmt::Display for BlankState {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match *self {
+			BlankState::Unblank => "unblank",
+			BlankState::Normal => "normal",
+			BlankState::VsyncSuspend => "vsync-suspend",
+			BlankState::HsyncSuspend => "hsync-suspend",
+			BlankState::Powerdown => "powerdown",
+		};
+		f.write_str(name)
+	}
+}
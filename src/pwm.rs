@@ -0,0 +1,276 @@
+//! Backlights wired directly to a PWM channel in /sys/class/pwm.
+//!
+//! On boards without a backlight driver the panel brightness is the duty
+//! cycle of a PWM channel, so the period plays the role of the maximum
+//! brightness and the duty cycle that of the brightness.  [`Pwm`] is a
+//! [`BacklightBackend`], so it is used through [`Brightness`]:
+//!
+//! ```no_run
+//! extern crate backlight;
+//! use backlight::{Brightness, Pwm};
+//! use backlight::pwm::Polarity;
+//!
+//! fn main() {
+//!     let pwm = Pwm::new(0, 1);
+//!     pwm.configure(50000, Polarity::Normal).unwrap();
+//!
+//!     let br = Brightness::from_backend(pwm);
+//!     br.set_percent(40).unwrap();
+//! }
+//! ```
+//!
+//! [`BacklightBackend`]: ../backend/trait.BacklightBackend.html
+//! [`Brightness`]: ../struct.Brightness.html
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use backend::BacklightBackend;
+use sysfs;
+use {BlankState, Error};
+
+/// The directory that the kernel publishes PWM chips in.
+pub const SYSFS_PWM: &str = "/sys/class/pwm";
+
+/// The polarity of a PWM channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Polarity {
+	/// The output is high for the duty cycle.
+	Normal,
+	/// The output is low for the duty cycle.
+	Inversed,
+}
+
+impl Polarity {
+	/// Parse the content of a `polarity` attribute.
+	pub fn parse(value: &str) -> Option<Self> {
+		match value.trim() {
+			"normal" => Some(Polarity::Normal),
+			"inversed" => Some(Polarity::Inversed),
+			_ => None,
+		}
+	}
+}
+
+impl fmt::Display for Polarity {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match *self {
+			Polarity::Normal => "normal",
+			Polarity::Inversed => "inversed",
+		};
+		f.write_str(name)
+	}
+}
+
+/// A backlight driven by a PWM channel, e.g. /sys/class/pwm/pwmchip0/pwm1.
+/// Enabling and disabling the channel serves as power control.
+#[derive(Debug, Clone)]
+pub struct Pwm {
+	chip: PathBuf,
+	channel: u32,
+}
+
+impl Pwm {
+	/// Create a new instance for channel `channel` of `pwmchip<chip>`.
+	pub fn new(chip: u32, channel: u32) -> Self {
+		Pwm::with_root(SYSFS_PWM, chip, channel)
+	}
+
+	/// Create a new instance for a PWM chip that lives in `root` rather
+	/// than /sys/class/pwm.
+	pub fn with_root<P: AsRef<Path>>(root: P, chip: u32, channel: u32) -> Self {
+		Pwm {
+			chip: root.as_ref().join(format!("pwmchip{}", chip)),
+			channel,
+		}
+	}
+
+	/// The directory of the exported channel.
+	pub fn path(&self) -> PathBuf {
+		self.chip.join(format!("pwm{}", self.channel))
+	}
+
+	/// Return true if the channel has been exported to user space.
+	pub fn is_exported(&self) -> bool {
+		self.path().is_dir()
+	}
+
+	/// Export the channel to user space, unless that has already happened.
+	pub fn export(&self) -> Result<(), Error> {
+		if self.is_exported() {
+			return Ok(());
+		}
+		sysfs::set(&self.chip, "export", &self.channel.to_string())
+	}
+
+	/// Hand the channel back to the kernel.
+	pub fn unexport(&self) -> Result<(), Error> {
+		sysfs::set(&self.chip, "unexport", &self.channel.to_string())
+	}
+
+	/// Export the channel if needed, then set its period and polarity and
+	/// enable it.  The duty cycle is limited to the new period first, as
+	/// the kernel rejects a period shorter than the duty cycle.
+	pub fn configure(&self, period: i32, polarity: Polarity) -> Result<(), Error> {
+		self.export()?;
+		if self.get_duty_cycle()? > period {
+			self.set_duty_cycle(period)?;
+		}
+		self.set_period(period)?;
+		if self.get_polarity()? != polarity {
+			self.set_enabled(false)?;
+			self.set_polarity(polarity)?;
+		}
+		self.set_enabled(true)
+	}
+
+	/// Return the period of the channel in nanoseconds.
+	pub fn get_period(&self) -> Result<i32, Error> {
+		sysfs::get(&self.path(), "period")
+	}
+
+	/// Set the period of the channel in nanoseconds.
+	pub fn set_period(&self, period: i32) -> Result<(), Error> {
+		sysfs::set(&self.path(), "period", &period.to_string())
+	}
+
+	/// Return the active time of each period in nanoseconds.
+	pub fn get_duty_cycle(&self) -> Result<i32, Error> {
+		sysfs::get(&self.path(), "duty_cycle")
+	}
+
+	/// Set the active time of each period in nanoseconds.
+	pub fn set_duty_cycle(&self, duty_cycle: i32) -> Result<(), Error> {
+		sysfs::set(&self.path(), "duty_cycle", &duty_cycle.to_string())
+	}
+
+	/// Return the polarity of the channel.
+	pub fn get_polarity(&self) -> Result<Polarity, Error> {
+		let value = sysfs::read(&self.path(), "polarity")?;
+		match Polarity::parse(&value) {
+			Some(polarity) => Ok(polarity),
+			None => Err(Error::MalformedAttribute {
+				path: self.path().join("polarity"),
+				content: value,
+			}),
+		}
+	}
+
+	/// Set the polarity of the channel.  Most drivers only accept this
+	/// while the channel is disabled.
+	pub fn set_polarity(&self, polarity: Polarity) -> Result<(), Error> {
+		sysfs::set(&self.path(), "polarity", &polarity.to_string())
+	}
+
+	/// Return true if the channel is producing output.
+	pub fn is_enabled(&self) -> Result<bool, Error> {
+		Ok(sysfs::get(&self.path(), "enable")? != 0)
+	}
+
+	/// Start or stop the output of the channel.
+	pub fn set_enabled(&self, enabled: bool) -> Result<(), Error> {
+		sysfs::set(&self.path(), "enable", if enabled { "1" } else { "0" })
+	}
+}
+
+impl BacklightBackend for Pwm {
+	fn read_level(&self) -> Result<i32, Error> {
+		self.get_duty_cycle()
+	}
+
+	fn read_max(&self) -> Result<i32, Error> {
+		self.get_period()
+	}
+
+	fn write_level(&self, value: i32) -> Result<(), Error> {
+		self.set_duty_cycle(value)
+	}
+
+	fn read_power(&self) -> Result<BlankState, Error> {
+		if self.is_enabled()? {
+			Ok(BlankState::Unblank)
+		} else {
+			Ok(BlankState::Powerdown)
+		}
+	}
+
+	fn write_power(&self, state: BlankState) -> Result<(), Error> {
+		self.set_enabled(state == BlankState::Unblank)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::*;
+	use testing::FakeSysfs;
+
+	#[test]
+	fn configure_lowers_the_duty_cycle_before_the_period() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let channel = sysfs.add_pwm(0, 0, 50000).unwrap();
+		channel.write("duty_cycle", 40000);
+		let pwm = Pwm::with_root(sysfs.pwm_root(), 0, 0);
+
+		// Make writing the period fail to see what happened before it.
+		channel.remove("period");
+		fs::create_dir(channel.path().join("period")).unwrap();
+		assert!(pwm.configure(20000, Polarity::Normal).is_err());
+		assert_eq!(channel.read("duty_cycle"), "20000");
+		fs::remove_dir(channel.path().join("period")).unwrap();
+		channel.write("period", 50000);
+
+		channel.write("duty_cycle", 40000);
+		pwm.configure(20000, Polarity::Normal).unwrap();
+		assert_eq!(channel.read("duty_cycle"), "20000");
+		assert_eq!(channel.read("period"), "20000");
+		assert_eq!(channel.read("enable"), "1");
+
+		pwm.configure(30000, Polarity::Normal).unwrap();
+		assert_eq!(channel.read("duty_cycle"), "20000");
+		assert_eq!(channel.read("period"), "30000");
+	}
+
+	#[test]
+	fn configure_disables_the_channel_to_change_polarity() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let channel = sysfs.add_pwm(0, 0, 50000).unwrap();
+		let pwm = Pwm::with_root(sysfs.pwm_root(), 0, 0);
+
+		// Make writing enable fail to see that the polarity waits for it.
+		channel.remove("enable");
+		fs::create_dir(channel.path().join("enable")).unwrap();
+		assert!(pwm.configure(50000, Polarity::Inversed).is_err());
+		assert_eq!(pwm.get_polarity().unwrap(), Polarity::Normal);
+		fs::remove_dir(channel.path().join("enable")).unwrap();
+		channel.write("enable", 1);
+
+		pwm.configure(50000, Polarity::Inversed).unwrap();
+		assert_eq!(pwm.get_polarity().unwrap(), Polarity::Inversed);
+		assert!(pwm.is_enabled().unwrap());
+
+		channel.write("polarity", "sideways");
+		match pwm.get_polarity() {
+			Err(Error::MalformedAttribute { content, .. }) => assert_eq!(content, "sideways"),
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+
+	#[test]
+	fn export_writes_the_channel_number_once() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let channel = sysfs.add_pwm(0, 0, 50000).unwrap();
+		let chip = channel.path().parent().unwrap().to_path_buf();
+
+		let exported = Pwm::with_root(sysfs.pwm_root(), 0, 0);
+		assert!(exported.is_exported());
+		exported.export().unwrap();
+		assert_eq!(fs::read_to_string(chip.join("export")).unwrap().trim(), "");
+
+		let other = Pwm::with_root(sysfs.pwm_root(), 0, 1);
+		assert!(!other.is_exported());
+		other.export().unwrap();
+		assert_eq!(fs::read_to_string(chip.join("export")).unwrap(), "1");
+	}
+}
@@ -0,0 +1,57 @@
+//! The `scale` attribute and perceptual brightness mapping.
+
+use std::fmt;
+
+/// How the raw brightness values of a device relate to its light output,
+/// as reported by the `scale` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scale {
+	/// Light output is proportional to the raw value.
+	Linear,
+	/// The raw values already follow a perceptual curve.
+	NonLinear,
+	/// The driver does not know, or the kernel predates `scale`.
+	Unknown,
+}
+
+impl Scale {
+	/// Parse the content of a `scale` attribute.
+	pub fn parse(value: &str) -> Self {
+		match value.trim() {
+			"linear" => Scale::Linear,
+			"non-linear" => Scale::NonLinear,
+			_ => Scale::Unknown,
+		}
+	}
+}
+
+impl fmt::Display for Scale {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match *self {
+			Scale::Linear => "linear",
+			Scale::NonLinear => "non-linear",
+			Scale::Unknown => "unknown",
+		};
+		f.write_str(name)
+	}
+}
+
+/// Convert a CIE 1931 lightness between 0 and 100 into a relative
+/// luminance between 0 and 1.
+pub fn lightness_to_luminance(lightness: f64) -> f64 {
+	if lightness <= 8.0 {
+		lightness / 903.3
+	} else {
+		((lightness + 16.0) / 116.0).powi(3)
+	}
+}
+
+/// Convert a relative luminance between 0 and 1 into a CIE 1931
+/// lightness between 0 and 100.
+pub fn luminance_to_lightness(luminance: f64) -> f64 {
+	if luminance <= 8.0 / 903.3 {
+		luminance * 903.3
+	} else {
+		116.0 * luminance.cbrt() - 16.0
+	}
+}
@@ -0,0 +1,244 @@
+//! Saving the brightness at shutdown and restoring it at boot.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use {Device, DeviceClass, Error, Limits};
+
+/// The directory that brightness is saved in by default.
+pub const STATE_DIR: &str = "/var/lib/backlight";
+
+/// Saved brightness levels, one file per device in a state directory.
+///
+/// Files are named after the device's [`id()`], so a device keeps its
+/// level when devices are probed in a different order or another device
+/// of the same name appears.  Restoring a backlight never goes below a
+/// minimum, 5% by default, so that a saved level of 0 cannot leave the
+/// screen black at boot.  LEDs, for which off is a normal state, are
+/// restored as saved.
+///
+/// [`id()`]: struct.Device.html#method.id
+#[derive(Debug, Clone)]
+pub struct StateStore {
+	dir: PathBuf,
+	min_percent: i32,
+}
+
+impl StateStore {
+	/// Keep saved levels in /var/lib/backlight.
+	pub fn new() -> Self {
+		StateStore::with_dir(STATE_DIR)
+	}
+
+	/// Keep saved levels in `dir`, which is created when first saving.
+	pub fn with_dir<P: AsRef<Path>>(dir: P) -> Self {
+		StateStore {
+			dir: dir.as_ref().to_path_buf(),
+			min_percent: 5,
+		}
+	}
+
+	/// Never restore a backlight below `percent` percent.  At least one
+	/// raw level is always restored, even with a minimum of 0.
+	pub fn min_percent(mut self, percent: i32) -> Self {
+		self.min_percent = percent.clamp(0, 100);
+		self
+	}
+
+	/// The directory holding the saved levels.
+	pub fn dir(&self) -> &Path {
+		&self.dir
+	}
+
+	/// The file that the level of `device` is saved in.
+	pub fn path_for(&self, device: &Device) -> PathBuf {
+		let name = device.id().replace('%', "%25").replace('/', "%2F");
+		self.dir.join(name)
+	}
+
+	/// Save the current brightness of `device` and return it.  The file is
+	/// replaced atomically, so a save interrupted at shutdown leaves the
+	/// previous level in place.
+	pub fn save(&self, device: &Device) -> Result<i32, Error> {
+		let value = device.open().get_brightness()?;
+		if let Err(err) = fs::create_dir_all(&self.dir) {
+			return Err(Error::from_io(err, &self.dir));
+		}
+		replace(&self.path_for(device), &format!("{}\n", value))?;
+		Ok(value)
+	}
+
+	/// Return the level saved for `device`, or `None` if it has never been
+	/// saved.
+	pub fn load(&self, device: &Device) -> Result<Option<i32>, Error> {
+		let path = self.path_for(device);
+		let content = match fs::read_to_string(&path) {
+			Ok(content) => content,
+			Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+			Err(err) => return Err(Error::from_io(err, &path)),
+		};
+		match content.trim().parse::<i32>() {
+			Ok(value) if value >= 0 => Ok(Some(value)),
+			_ => Err(Error::MalformedAttribute {
+				path,
+				content: content.trim().to_string(),
+			}),
+		}
+	}
+
+	/// Set `device` to its saved level, raised to the minimum for
+	/// backlights and limited to the device's maximum.  Returns the level
+	/// set, or `None` if nothing was saved and the device was left alone.
+	pub fn restore(&self, device: &Device) -> Result<Option<i32>, Error> {
+		let saved = match self.load(device)? {
+			Some(saved) => saved,
+			None => return Ok(None),
+		};
+		let mut brightness = device.open();
+		if device.class() == DeviceClass::Backlight {
+			brightness.set_limits(Limits::new().min_raw(1).min_percent(self.min_percent));
+		}
+		brightness.set_brightness(saved)?;
+		Ok(Some(brightness.get_brightness()?))
+	}
+}
+
+/// Counts temporary files, so that concurrent saves within a process use
+/// different names.
+static NEXT_TEMPORARY: AtomicUsize = AtomicUsize::new(0);
+
+/// Replace the file at `path` with `content` by writing a temporary file
+/// next to it, flushing it to disk and renaming it over `path`.  A crash
+/// or power cut at any point leaves either the old or the new content.
+pub(crate) fn replace(path: &Path, content: &str) -> Result<(), Error> {
+	let mut temporary = path.as_os_str().to_owned();
+	temporary.push(format!(".{}.{}.tmp", process::id(), NEXT_TEMPORARY.fetch_add(1, Ordering::SeqCst)));
+	let temporary = PathBuf::from(temporary);
+	if let Err(err) = write_synced(&temporary, content) {
+		let _ = fs::remove_file(&temporary);
+		return Err(Error::from_io(err, &temporary));
+	}
+	if let Err(err) = fs::rename(&temporary, path) {
+		let _ = fs::remove_file(&temporary);
+		return Err(Error::from_io(err, path));
+	}
+	// Flush the directory too, so that the rename itself survives.
+	let dir = match path.parent() {
+		Some(dir) if dir != Path::new("") => dir,
+		_ => Path::new("."),
+	};
+	match File::open(dir).and_then(|dir| dir.sync_all()) {
+		Ok(()) => Ok(()),
+		Err(err) => Err(Error::from_io(err, dir)),
+	}
+}
+
+/// Write `content` to a new file at `path` and wait until it is on disk.
+fn write_synced(path: &Path, content: &str) -> Result<(), io::Error> {
+	let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+	file.write_all(content.as_bytes())?;
+	file.sync_all()
+}
+
+impl Default for StateStore {
+	fn default() -> Self {
+		StateStore::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::thread;
+
+	use super::*;
+	use testing::FakeSysfs;
+	use {BacklightType, Brightness};
+
+	#[test]
+	fn id_uses_the_parent_device() {
+		let sysfs = FakeSysfs::new().unwrap();
+		sysfs.add_backlight("lcd", BacklightType::Raw, 200).unwrap();
+		let device = &Brightness::list_in(sysfs.backlight_root()).unwrap()[0];
+		assert_eq!(device.id(), "platform/lcd/backlight:backlight:lcd");
+
+		let store = StateStore::with_dir("/state");
+		assert_eq!(store.path_for(device), Path::new("/state/platform%2Flcd%2Fbacklight:backlight:lcd"));
+	}
+
+	#[test]
+	fn restore_never_blacks_out_a_backlight() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let lcd = sysfs.add_backlight("lcd", BacklightType::Raw, 200).unwrap();
+		let device = &Brightness::list_in(sysfs.backlight_root()).unwrap()[0];
+		let store = StateStore::with_dir(sysfs.path().join("state"));
+		assert_eq!(store.restore(device).unwrap(), None);
+
+		lcd.write("brightness", 0);
+		assert_eq!(store.save(device).unwrap(), 0);
+		assert_eq!(store.load(device).unwrap(), Some(0));
+
+		// A saved 0 is restored as 5% so that the screen stays visible.
+		lcd.write("brightness", 200);
+		assert_eq!(store.restore(device).unwrap(), Some(10));
+		assert_eq!(lcd.read("brightness"), "10");
+
+		lcd.write("brightness", 150);
+		store.save(device).unwrap();
+		lcd.write("brightness", 20);
+		assert_eq!(store.restore(device).unwrap(), Some(150));
+	}
+
+	#[test]
+	fn leds_are_restored_as_saved() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let led = sysfs.add_led("kbd_backlight", 3).unwrap();
+		let device = &Brightness::list_leds_in(sysfs.leds_root()).unwrap()[0];
+		let store = StateStore::with_dir(sysfs.path().join("state"));
+
+		led.write("brightness", 0);
+		store.save(device).unwrap();
+		led.write("brightness", 3);
+		assert_eq!(store.restore(device).unwrap(), Some(0));
+	}
+
+	#[test]
+	fn save_replaces_the_file_atomically() {
+		let sysfs = FakeSysfs::new().unwrap();
+		sysfs.add_backlight("lcd", BacklightType::Raw, 200).unwrap();
+		let device = &Brightness::list_in(sysfs.backlight_root()).unwrap()[0];
+		let store = StateStore::with_dir(sysfs.path().join("state"));
+
+		store.save(device).unwrap();
+		let entries: Vec<_> = fs::read_dir(store.dir()).unwrap().collect();
+		assert_eq!(entries.len(), 1);
+		assert_eq!(fs::read_to_string(store.path_for(device)).unwrap(), "200\n");
+
+		fs::write(store.path_for(device), "").unwrap();
+		match store.load(device) {
+			Err(Error::MalformedAttribute { .. }) => {}
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+
+	#[test]
+	fn concurrent_saves_do_not_share_a_temporary_file() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let path = sysfs.path().join("state");
+		let threads: Vec<_> = (0..8).map(|i| {
+			let path = path.clone();
+			thread::spawn(move || replace(&path, &format!("{}\n", i)))
+		}).collect();
+		for thread in threads {
+			thread.join().unwrap().unwrap();
+		}
+
+		let content = fs::read_to_string(&path).unwrap();
+		let value: i32 = content.trim().parse().unwrap();
+		assert!((0..8).contains(&value));
+		let names: Vec<_> = fs::read_dir(sysfs.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+		assert!(names.iter().all(|name| !name.to_string_lossy().ends_with(".tmp")));
+	}
+}
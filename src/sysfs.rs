@@ -0,0 +1,120 @@
+//! Reading and writing sysfs attribute files.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use Error;
+
+/// Read the trimmed content of an attribute within `dir`.
+pub fn read(dir: &Path, filename: &str) -> Result<String, Error> {
+	let path = dir.join(filename);
+	match fs::read_to_string(&path) {
+		Ok(content) => Ok(content.trim().to_string()),
+		Err(err) => Err(Error::from_io(err, &path)),
+	}
+}
+
+/// Read an attribute within `dir` holding a single integer.
+pub fn get(dir: &Path, filename: &str) -> Result<i32, Error> {
+	let content = read(dir, filename)?;
+	match content.parse::<i32>() {
+		Ok(value) => Ok(value),
+		Err(_) => Err(Error::MalformedAttribute {
+			path: dir.join(filename),
+			content,
+		}),
+	}
+}
+
+/// Write a value to an attribute within `dir`.
+pub fn set(dir: &Path, filename: &str, value: &str) -> Result<(), Error> {
+	let path = dir.join(filename);
+	let mut file = match OpenOptions::new().write(true).truncate(true).open(&path) {
+		Ok(file) => file,
+		Err(err) => return Err(Error::from_io(err, &path)),
+	};
+
+	match file.write_all(value.as_bytes()) {
+		Ok(_) => Ok(()),
+		Err(source) => Err(Error::WriteRejected {
+			path,
+			value: value.to_string(),
+			source,
+		}),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io;
+	use std::os::unix::fs::PermissionsExt;
+
+	use libc;
+
+	use super::*;
+	use testing::FakeSysfs;
+	use BacklightType;
+
+	#[test]
+	fn missing_attributes_are_not_found() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let device = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+		match get(device.path(), "scale") {
+			Err(Error::DeviceNotFound(path)) => assert_eq!(path, device.path().join("scale")),
+			other => panic!("unexpected {:?}", other),
+		}
+		match set(device.path(), "scale", "linear") {
+			Err(Error::DeviceNotFound(path)) => assert_eq!(path, device.path().join("scale")),
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+
+	#[test]
+	fn malformed_attributes_keep_their_content() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let device = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+		device.write("brightness", " bright ");
+		assert_eq!(read(device.path(), "brightness").unwrap(), "bright");
+		match get(device.path(), "brightness") {
+			Err(Error::MalformedAttribute { path, content }) => {
+				assert_eq!(path, device.path().join("brightness"));
+				assert_eq!(content, "bright");
+			}
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+
+	#[test]
+	fn unreadable_attributes_are_permission_denied() {
+		let path = Path::new("/sys/class/backlight/lcd/brightness");
+		match Error::from_io(io::Error::from(io::ErrorKind::PermissionDenied), path) {
+			Error::PermissionDenied(denied) => assert_eq!(denied, path),
+			other => panic!("unexpected {:?}", other),
+		}
+
+		// Root can read files regardless of their mode.
+		if unsafe { libc::geteuid() } == 0 {
+			return;
+		}
+		let sysfs = FakeSysfs::new().unwrap();
+		let device = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+		fs::set_permissions(device.path().join("brightness"), fs::Permissions::from_mode(0o000)).unwrap();
+		match get(device.path(), "brightness") {
+			Err(Error::PermissionDenied(denied)) => assert_eq!(denied, device.path().join("brightness")),
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+
+	#[test]
+	fn failed_writes_are_rejected() {
+		// Writing to /dev/full fails the way a driver rejecting a value does.
+		match set(Path::new("/dev"), "full", "42") {
+			Err(Error::WriteRejected { path, value, .. }) => {
+				assert_eq!(path, Path::new("/dev/full"));
+				assert_eq!(value, "42");
+			}
+			other => panic!("unexpected {:?}", other),
+		}
+	}
+}
@@ -0,0 +1,608 @@
+//! Helpers for testing code that uses this crate without real hardware.
+//!
+//! Enabled with the `testing` cargo feature.  [`FakeSysfs`] builds a fake
+//! sysfs tree for the sysfs backed devices, while [`MockBackend`] keeps its
+//! state in memory and records every write.  With the `logind` feature,
+//! [`MockLogind`] stands in for systemd-logind.
+//!
+//! ```
+//! extern crate backlight;
+//! use backlight::{BacklightType, Brightness};
+//! use backlight::testing::FakeSysfs;
+//!
+//! fn main() {
+//!     let sysfs = FakeSysfs::new().unwrap();
+//!     let lcd = sysfs.add_backlight("intel_backlight", BacklightType::Raw, 1000).unwrap();
+//!
+//!     let br = Brightness::with_root(sysfs.backlight_root(), "intel_backlight");
+//!     br.set_percent(25).unwrap();
+//!     assert_eq!(lcd.read("brightness"), "250");
+//! }
+//! ```
+
+use std::cell::Cell;
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use backend::BacklightBackend;
+use fade::Clock;
+use {BacklightType, BlankState, Error};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary directory laid out like /sys.  Devices live under `devices/`
+/// and are linked from `class/<class>/`, as they are in the real sysfs.  The
+/// directory is removed when the value is dropped.
+pub struct FakeSysfs {
+	root: PathBuf,
+}
+
+impl FakeSysfs {
+	/// Create an empty tree in the system temporary directory.
+	pub fn new() -> Result<Self, io::Error> {
+		let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+		let root = env::temp_dir().join(format!("backlight-sysfs-{}-{}", process::id(), id));
+		if root.exists() {
+			fs::remove_dir_all(&root)?;
+		}
+		fs::create_dir_all(root.join("class/backlight"))?;
+		fs::create_dir_all(root.join("class/leds"))?;
+		Ok(FakeSysfs { root })
+	}
+
+	/// The directory standing in for /sys.
+	pub fn path(&self) -> &Path {
+		&self.root
+	}
+
+	/// The directory standing in for /sys/class/backlight.  Pass this to
+	/// [`Brightness::with_root()`](../struct.Brightness.html#method.with_root).
+	pub fn backlight_root(&self) -> PathBuf {
+		self.root.join("class/backlight")
+	}
+
+	/// The directory standing in for /sys/class/leds.
+	pub fn leds_root(&self) -> PathBuf {
+		self.root.join("class/leds")
+	}
+
+	/// Add an LED device, named `devicename:color:function`, with the
+	/// `brightness`, `max_brightness` and `trigger` attributes.  The LED
+	/// starts off, with no trigger.
+	pub fn add_led(&self, name: &str, max_brightness: i32) -> Result<FakeDevice, io::Error> {
+		let parent = format!("platform/{}", name.split(':').next().unwrap_or(name));
+		let device = self.add_device(&parent, "leds", name)?;
+		device.write("max_brightness", max_brightness);
+		device.write("brightness", 0);
+		device.write("trigger", "[none] timer heartbeat default-on");
+		Ok(device)
+	}
+
+	/// Add a multicolor LED with one channel per entry of `channels`,
+	/// e.g. `["red", "green", "blue"]`, each at full intensity.
+	pub fn add_multicolor_led(&self, name: &str, channels: &[&str], max_brightness: i32) -> Result<FakeDevice, io::Error> {
+		let device = self.add_led(name, max_brightness)?;
+		let intensities: Vec<String> = channels.iter().map(|_| max_brightness.to_string()).collect();
+		device.write("multi_index", channels.join(" "));
+		device.write("multi_intensity", intensities.join(" "));
+		Ok(device)
+	}
+
+	/// The directory standing in for /sys/class/pwm.
+	pub fn pwm_root(&self) -> PathBuf {
+		self.root.join("class/pwm")
+	}
+
+	/// Add `pwmchip<chip>` with an already exported channel `channel`,
+	/// whose `period` is `period` and whose output starts disabled at a
+	/// duty cycle of zero.  The returned device is the channel directory.
+	pub fn add_pwm(&self, chip: u32, channel: u32, period: i32) -> Result<FakeDevice, io::Error> {
+		let chip_name = format!("pwmchip{}", chip);
+		let chip_dir = self.root.join("class/pwm").join(&chip_name);
+		let chip_device = if chip_dir.exists() {
+			FakeDevice { path: fs::canonicalize(chip_dir)? }
+		} else {
+			let chip_device = self.add_device(&format!("platform/pwm{}", chip), "pwm", &chip_name)?;
+			chip_device.write("npwm", channel + 1);
+			chip_device.write("export", "");
+			chip_device.write("unexport", "");
+			chip_device
+		};
+
+		let path = chip_device.path.join(format!("pwm{}", channel));
+		fs::create_dir_all(&path)?;
+		let device = FakeDevice { path };
+		device.write("period", period);
+		device.write("duty_cycle", 0);
+		device.write("polarity", "normal");
+		device.write("enable", 0);
+		Ok(device)
+	}
+
+	/// The directory standing in for /sys/bus/iio/devices.
+	pub fn iio_root(&self) -> PathBuf {
+		self.root.join("bus/iio/devices")
+	}
+
+	/// Add the ambient light sensor `iio:device<index>`, reporting
+	/// `(raw + offset) * scale` lux through `in_illuminance_raw`,
+	/// `in_illuminance_offset` and `in_illuminance_scale`.  Update the raw
+	/// reading with `write("in_illuminance_raw", ...)`.
+	pub fn add_light_sensor(&self, index: u32, raw: i32, scale: f64, offset: f64) -> Result<FakeDevice, io::Error> {
+		let name = format!("iio:device{}", index);
+		let path = self.root.join("devices/platform").join(format!("als{}", index)).join(&name);
+		fs::create_dir_all(&path)?;
+
+		let bus_dir = self.iio_root();
+		fs::create_dir_all(&bus_dir)?;
+		symlink(&path, bus_dir.join(&name))?;
+
+		let device = FakeDevice { path };
+		device.write("name", "als");
+		device.write("in_illuminance_raw", raw);
+		device.write("in_illuminance_scale", scale);
+		device.write("in_illuminance_offset", offset);
+		Ok(device)
+	}
+
+	/// Add a backlight device with the `brightness`, `actual_brightness`,
+	/// `max_brightness`, `type` and `bl_power` attributes.  The device
+	/// starts powered on at full brightness.
+	pub fn add_backlight(&self, name: &str, kind: BacklightType, max_brightness: i32) -> Result<FakeDevice, io::Error> {
+		let parent = format!("platform/{}", name);
+		let device = self.add_device(&parent, "backlight", name)?;
+		device.write("max_brightness", max_brightness);
+		device.write("brightness", max_brightness);
+		device.write("actual_brightness", max_brightness);
+		device.write("type", kind);
+		device.write("bl_power", 0);
+		Ok(device)
+	}
+
+	/// Create an empty device directory below `devices/<parent>/<class>/`
+	/// and link it into `class/<class>/`.
+	pub fn add_device(&self, parent: &str, class: &str, name: &str) -> Result<FakeDevice, io::Error> {
+		let path = self.root.join("devices").join(parent).join(class).join(name);
+		fs::create_dir_all(&path)?;
+
+		let class_dir = self.root.join("class").join(class);
+		fs::create_dir_all(&class_dir)?;
+		symlink(&path, class_dir.join(name))?;
+
+		Ok(FakeDevice { path })
+	}
+}
+
+impl Drop for FakeSysfs {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.root);
+	}
+}
+
+/// A device directory within a [`FakeSysfs`] tree.
+#[derive(Debug, Clone)]
+pub struct FakeDevice {
+	path: PathBuf,
+}
+
+impl FakeDevice {
+	/// The directory holding the device's attributes.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Return the trimmed content of an attribute, or an empty string
+	/// if it does not exist.
+	pub fn read(&self, attribute: &str) -> String {
+		match fs::read_to_string(self.path.join(attribute)) {
+			Ok(value) => value.trim().to_string(),
+			Err(_) => String::new(),
+		}
+	}
+
+	/// Create or replace an attribute.  Panics if the file cannot be
+	/// written, as that means the test setup itself is broken.
+	pub fn write<T: ToString>(&self, attribute: &str, value: T) {
+		let path = self.path.join(attribute);
+		if let Err(err) = fs::write(&path, format!("{}\n", value.to_string())) {
+			panic!("cannot write {}: {}", path.display(), err);
+		}
+	}
+
+	/// Remove an attribute, e.g. to emulate an older kernel.
+	pub fn remove(&self, attribute: &str) {
+		let _ = fs::remove_file(self.path.join(attribute));
+	}
+}
+
+/// A clock for fades that never blocks: sleeping advances the time
+/// immediately.
+///
+/// ```
+/// extern crate backlight;
+/// use std::time::Duration;
+/// use backlight::{BacklightType, Brightness, Fade, FadeOutcome};
+/// use backlight::fade::Clock;
+/// use backlight::testing::{FakeSysfs, VirtualClock};
+///
+/// fn main() {
+///     let sysfs = FakeSysfs::new().unwrap();
+///     let lcd = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+///     let br = Brightness::with_root(sysfs.backlight_root(), "lcd");
+///
+///     let clock = VirtualClock::new();
+///     let fade = Fade::new(Duration::from_secs(2));
+///     assert_eq!(br.fade(0, &fade, &clock).unwrap(), FadeOutcome::Completed);
+///     assert_eq!(lcd.read("brightness"), "0");
+///     assert_eq!(clock.now(), Duration::from_secs(2));
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct VirtualClock {
+	now: Cell<Duration>,
+}
+
+impl VirtualClock {
+	/// Create a clock starting at zero.
+	pub fn new() -> Self {
+		VirtualClock::default()
+	}
+
+	/// Move the clock forward without sleeping.
+	pub fn advance(&self, duration: Duration) {
+		self.now.set(self.now.get() + duration);
+	}
+}
+
+impl Clock for VirtualClock {
+	fn now(&self) -> Duration {
+		self.now.get()
+	}
+
+	fn sleep(&self, duration: Duration) {
+		self.advance(duration);
+	}
+}
+
+/// A failure that [`MockBackend`] can be told to simulate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+	/// The device has disappeared: [`Error::DeviceNotFound`].
+	NotFound,
+	/// The process lacks access: [`Error::PermissionDenied`].
+	PermissionDenied,
+	/// A write is refused by the driver: [`Error::WriteRejected`].  For
+	/// reads this behaves as a generic I/O error.
+	Rejected,
+}
+
+impl Failure {
+	fn error(self, attribute: &str, value: Option<i32>) -> Error {
+		let path = Path::new("mock").join(attribute);
+		match (self, value) {
+			(Failure::NotFound, _) => Error::DeviceNotFound(path),
+			(Failure::PermissionDenied, _) => Error::PermissionDenied(path),
+			(Failure::Rejected, Some(value)) => Error::WriteRejected {
+				path,
+				value: value.to_string(),
+				source: io::Error::from_raw_os_error(22),
+			},
+			(Failure::Rejected, None) => Error::Io(io::Error::from_raw_os_error(5)),
+		}
+	}
+}
+
+#[derive(Debug)]
+struct MockState {
+	level: i32,
+	actual: i32,
+	max: i32,
+	power: BlankState,
+	clamp: Option<(i32, i32)>,
+	read_failure: Option<Failure>,
+	write_failure: Option<Failure>,
+	writes: Vec<i32>,
+}
+
+/// An in-memory backend for testing.  Clones share the same device, so a
+/// test can keep one handle for assertions while a [`Brightness`] owns
+/// another.
+///
+/// [`Brightness`]: ../struct.Brightness.html
+///
+/// ```
+/// extern crate backlight;
+/// use std::time::Duration;
+/// use backlight::{Brightness, Fade};
+/// use backlight::testing::{MockBackend, VirtualClock};
+///
+/// fn main() {
+///     let mock = MockBackend::new(10);
+///     let br = Brightness::from_backend(mock.clone());
+///
+///     let fade = Fade::new(Duration::from_millis(100)).rate(50);
+///     br.fade(0, &fade, &VirtualClock::new()).unwrap();
+///     assert_eq!(mock.writes(), vec![8, 6, 4, 2, 0]);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockBackend {
+	state: Arc<Mutex<MockState>>,
+}
+
+impl MockBackend {
+	/// Create a powered on device at full brightness.
+	pub fn new(max_brightness: i32) -> Self {
+		MockBackend {
+			state: Arc::new(Mutex::new(MockState {
+				level: max_brightness,
+				actual: max_brightness,
+				max: max_brightness,
+				power: BlankState::Unblank,
+				clamp: None,
+				read_failure: None,
+				write_failure: None,
+				writes: Vec::new(),
+			})),
+		}
+	}
+
+	fn state(&self) -> MutexGuard<'_, MockState> {
+		match self.state.lock() {
+			Ok(state) => state,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	/// Every level written through the backend, oldest first.
+	pub fn writes(&self) -> Vec<i32> {
+		self.state().writes.clone()
+	}
+
+	/// Forget the recorded writes.
+	pub fn clear_writes(&self) {
+		self.state().writes.clear();
+	}
+
+	/// Change the level as if something other than the backend had done
+	/// so, e.g. a hotkey handled by the firmware.  This is not recorded
+	/// as a write.
+	pub fn set_level(&self, level: i32) {
+		let mut state = self.state();
+		state.level = level;
+		state.actual = level;
+	}
+
+	/// Change the maximum brightness, e.g. to emulate a driver reload.
+	pub fn set_max(&self, max_brightness: i32) {
+		self.state().max = max_brightness;
+	}
+
+	/// Emulate firmware that only honours levels between `min` and `max`:
+	/// written levels are recorded as requested, but the actual level is
+	/// clamped to the range.  `None` removes the limit.
+	pub fn set_clamp(&self, range: Option<(i32, i32)>) {
+		self.state().clamp = range;
+	}
+
+	/// Make every read fail with `failure`, or succeed again with `None`.
+	pub fn set_read_failure(&self, failure: Option<Failure>) {
+		self.state().read_failure = failure;
+	}
+
+	/// Make every write fail with `failure`, or succeed again with `None`.
+	/// Failed writes are not recorded.
+	pub fn set_write_failure(&self, failure: Option<Failure>) {
+		self.state().write_failure = failure;
+	}
+
+	fn read<F: Fn(&MockState) -> T, T>(&self, attribute: &str, read: F) -> Result<T, Error> {
+		let state = self.state();
+		match state.read_failure {
+			Some(failure) => Err(failure.error(attribute, None)),
+			None => Ok(read(&state)),
+		}
+	}
+}
+
+impl BacklightBackend for MockBackend {
+	fn read_level(&self) -> Result<i32, Error> {
+		self.read("brightness", |state| state.level)
+	}
+
+	fn read_max(&self) -> Result<i32, Error> {
+		self.read("max_brightness", |state| state.max)
+	}
+
+	fn write_level(&self, value: i32) -> Result<(), Error> {
+		let mut state = self.state();
+		if let Some(failure) = state.write_failure {
+			return Err(failure.error("brightness", Some(value)));
+		}
+		state.writes.push(value);
+		state.level = value;
+		state.actual = match state.clamp {
+			Some((min, max)) => value.clamp(min, max),
+			None => value,
+		};
+		Ok(())
+	}
+
+	fn read_actual_level(&self) -> Result<i32, Error> {
+		self.read("actual_brightness", |state| state.actual)
+	}
+
+	fn read_power(&self) -> Result<BlankState, Error> {
+		self.read("bl_power", |state| state.power)
+	}
+
+	fn write_power(&self, power: BlankState) -> Result<(), Error> {
+		let mut state = self.state();
+		if let Some(failure) = state.write_failure {
+			return Err(failure.error("bl_power", Some(power.value())));
+		}
+		state.power = power;
+		Ok(())
+	}
+}
+
+#[cfg(feature = "logind")]
+#[derive(Debug, Default)]
+struct LogindState {
+	calls: Vec<(String, String, u32)>,
+	failure: Option<Failure>,
+}
+
+/// The `org.freedesktop.login1.Session` interface served by [`MockLogind`].
+#[cfg(feature = "logind")]
+struct MockSession {
+	root: PathBuf,
+	state: Arc<Mutex<LogindState>>,
+}
+
+#[cfg(feature = "logind")]
+#[zbus::dbus_interface(name = "org.freedesktop.login1.Session")]
+impl MockSession {
+	fn set_brightness(&self, subsystem: &str, name: &str, brightness: u32) -> zbus::fdo::Result<()> {
+		let mut state = match self.state.lock() {
+			Ok(state) => state,
+			Err(poisoned) => poisoned.into_inner(),
+		};
+		match state.failure {
+			Some(Failure::NotFound) => return Err(zbus::fdo::Error::FileNotFound(name.to_string())),
+			Some(Failure::PermissionDenied) => return Err(zbus::fdo::Error::AccessDenied("Not in active session".to_string())),
+			Some(Failure::Rejected) => return Err(zbus::fdo::Error::Failed("Failed to write brightness".to_string())),
+			None => {}
+		}
+		if subsystem != "backlight" && subsystem != "leds" {
+			return Err(zbus::fdo::Error::InvalidArgs(format!("Subsystem type {} not supported", subsystem)));
+		}
+		let path = self.root.join("class").join(subsystem).join(name);
+		let max = fs::read_to_string(path.join("max_brightness")).ok()
+			.and_then(|max| max.trim().parse::<u32>().ok());
+		let max = match max {
+			Some(max) => max,
+			None => return Err(zbus::fdo::Error::FileNotFound(path.display().to_string())),
+		};
+		if let Err(err) = fs::write(path.join("brightness"), brightness.min(max).to_string()) {
+			return Err(zbus::fdo::Error::IOError(err.to_string()));
+		}
+		state.calls.push((subsystem.to_string(), name.to_string(), brightness));
+		Ok(())
+	}
+}
+
+/// A stand-in for systemd-logind that serves a session's `SetBrightness`
+/// method by writing to the devices of a [`FakeSysfs`] tree.  Enabled with
+/// both the `testing` and `logind` cargo features.
+///
+/// ```
+/// extern crate backlight;
+/// use backlight::{BacklightType, Brightness, Error, Logind};
+/// use backlight::logind::AUTO_SESSION;
+/// use backlight::testing::{Failure, FakeSysfs, MockLogind};
+///
+/// fn main() {
+///     let sysfs = FakeSysfs::new().unwrap();
+///     let lcd = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+///     let device = &Brightness::list_in(sysfs.backlight_root()).unwrap()[0];
+///
+///     let (mock, connection) = MockLogind::new(&sysfs).unwrap();
+///     let br = Brightness::from_backend(Logind::with_connection(connection, AUTO_SESSION, device));
+///     br.set_percent(30).unwrap();
+///     assert_eq!(lcd.read("brightness"), "30");
+///     assert_eq!(mock.calls(), vec![("backlight".to_string(), "lcd".to_string(), 30)]);
+///
+///     mock.set_failure(Some(Failure::PermissionDenied));
+///     match br.set_percent(50) {
+///         Err(Error::PermissionDenied(_)) => {}
+///         other => panic!("unexpected {:?}", other),
+///     }
+/// }
+/// ```
+#[cfg(feature = "logind")]
+pub struct MockLogind {
+	state: Arc<Mutex<LogindState>>,
+	_connection: zbus::blocking::Connection,
+}
+
+#[cfg(feature = "logind")]
+impl MockLogind {
+	/// Serve the session at [`AUTO_SESSION`] over a private peer-to-peer
+	/// connection.  Returns the mock and the other end of the connection,
+	/// to be passed to [`Logind::with_connection()`].
+	///
+	/// [`AUTO_SESSION`]: ../logind/constant.AUTO_SESSION.html
+	/// [`Logind::with_connection()`]: ../logind/struct.Logind.html#method.with_connection
+	pub fn new(sysfs: &FakeSysfs) -> Result<(Self, zbus::blocking::Connection), Error> {
+		use std::os::unix::net::UnixStream;
+		use std::thread;
+		use zbus::blocking::ConnectionBuilder;
+
+		let (server, client) = UnixStream::pair()?;
+		let state = Arc::new(Mutex::new(LogindState::default()));
+		let session = MockSession {
+			root: sysfs.path().to_path_buf(),
+			state: state.clone(),
+		};
+		let serving = thread::spawn(move || {
+			let guid = zbus::Guid::generate();
+			ConnectionBuilder::unix_stream(server).server(&guid).p2p()
+				.serve_at(::logind::AUTO_SESSION, session)?
+				.build()
+		});
+		let client = ConnectionBuilder::unix_stream(client).p2p().build().map_err(::logind::dbus_error)?;
+		let connection = match serving.join() {
+			Ok(result) => result.map_err(::logind::dbus_error)?,
+			Err(_) => return Err(Error::Io(io::Error::other("mock logind thread panicked"))),
+		};
+		Ok((MockLogind { state, _connection: connection }, client))
+	}
+
+	/// Serve the session at [`AUTO_SESSION`] under the name
+	/// `org.freedesktop.login1` on the bus at `address`, such as a private
+	/// `dbus-daemon --session`, for use with [`Logind::with_address()`].
+	///
+	/// [`AUTO_SESSION`]: ../logind/constant.AUTO_SESSION.html
+	/// [`Logind::with_address()`]: ../logind/struct.Logind.html#method.with_address
+	pub fn on_bus(sysfs: &FakeSysfs, address: &str) -> Result<Self, Error> {
+		let state = Arc::new(Mutex::new(LogindState::default()));
+		let session = MockSession {
+			root: sysfs.path().to_path_buf(),
+			state: state.clone(),
+		};
+		let connection = zbus::blocking::ConnectionBuilder::address(address)
+			.and_then(|builder| builder.name("org.freedesktop.login1"))
+			.and_then(|builder| builder.serve_at(::logind::AUTO_SESSION, session))
+			.and_then(|builder| builder.build())
+			.map_err(::logind::dbus_error)?;
+		Ok(MockLogind { state, _connection: connection })
+	}
+
+	fn state(&self) -> MutexGuard<'_, LogindState> {
+		match self.state.lock() {
+			Ok(state) => state,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	/// Every successful `SetBrightness` call as `(subsystem, name, value)`,
+	/// oldest first.
+	pub fn calls(&self) -> Vec<(String, String, u32)> {
+		self.state().calls.clone()
+	}
+
+	/// Make every call fail, or succeed again with `None`.
+	/// [`Failure::PermissionDenied`] answers as logind does for a session
+	/// that is not active.
+	pub fn set_failure(&self, failure: Option<Failure>) {
+		self.state().failure = failure;
+	}
+}
@@ -0,0 +1,81 @@
+//! The `trigger` attribute of LED devices.
+
+use std::fmt;
+
+/// The triggers an LED supports and the one currently driving it, parsed
+/// from a `trigger` attribute such as `none [timer] heartbeat`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Triggers {
+	available: Vec<String>,
+	active: Option<String>,
+}
+
+impl Triggers {
+	/// Parse the content of a `trigger` attribute.  The active trigger is
+	/// the one in square brackets.
+	pub fn parse(value: &str) -> Self {
+		let mut available = Vec::new();
+		let mut active = None;
+		for word in value.split_whitespace() {
+			if word.starts_with('[') && word.ends_with(']') && word.len() > 2 {
+				let name = word[1..word.len() - 1].to_string();
+				active = Some(name.clone());
+				available.push(name);
+			} else {
+				available.push(word.to_string());
+			}
+		}
+		Triggers { available, active }
+	}
+
+	/// Every trigger the LED can be attached to.
+	pub fn available(&self) -> &[String] {
+		&self.available
+	}
+
+	/// The active trigger, if the kernel marked one.
+	pub fn active(&self) -> Option<&str> {
+		self.active.as_deref()
+	}
+
+	/// Return true if `name` is one of the available triggers.
+	pub fn contains(&self, name: &str) -> bool {
+		self.available.iter().any(|trigger| trigger == name)
+	}
+}
+
+impl fmt::Display for Triggers {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let words: Vec<String> = self.available.iter().map(|name| {
+			if Some(name) == self.active.as_ref() {
+				format!("[{}]", name)
+			} else {
+				name.clone()
+			}
+		}).collect();
+		f.write_str(&words.join(" "))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_marks_the_bracketed_trigger_active() {
+		let triggers = Triggers::parse("none [timer] heartbeat default-on\n");
+		assert_eq!(triggers.available(), ["none", "timer", "heartbeat", "default-on"]);
+		assert_eq!(triggers.active(), Some("timer"));
+		assert!(triggers.contains("heartbeat"));
+		assert!(!triggers.contains("[timer]"));
+		assert_eq!(triggers.to_string(), "none [timer] heartbeat default-on");
+	}
+
+	#[test]
+	fn parse_without_an_active_trigger() {
+		let triggers = Triggers::parse("none timer []");
+		assert_eq!(triggers.active(), None);
+		assert_eq!(triggers.available(), ["none", "timer", "[]"]);
+		assert!(Triggers::parse("").available().is_empty());
+	}
+}
@@ -0,0 +1,245 @@
+//! Notification of brightness changes made by other processes, hotkeys
+//! handled by the firmware and the like.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use libc;
+
+use Error;
+
+/// A change of brightness seen by a [`Watcher`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+	/// The requested brightness, `brightness`, changed to the given level.
+	Brightness(i32),
+	/// The brightness reported by the hardware, `actual_brightness`,
+	/// changed to the given level.
+	ActualBrightness(i32),
+}
+
+struct Attribute {
+	name: &'static str,
+	file: File,
+	value: i32,
+}
+
+/// Waits for the `brightness` and `actual_brightness` attributes of a
+/// device to change.
+///
+/// Drivers that call `sysfs_notify()` wake the watcher as soon as the
+/// value changes.  As many drivers do not, the attributes are also read
+/// again at a fixed interval, half a second by default, so that no change
+/// goes unnoticed for longer than that.
+pub struct Watcher {
+	path: PathBuf,
+	attributes: Vec<Attribute>,
+	interval: Duration,
+	pending: VecDeque<Change>,
+}
+
+impl Watcher {
+	/// Watch the device in the given sysfs directory.  Devices without an
+	/// `actual_brightness` attribute, such as LEDs, only report
+	/// [`Change::Brightness`].
+	///
+	/// [`Change::Brightness`]: enum.Change.html#variant.Brightness
+	pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+		let path = path.as_ref().to_path_buf();
+		let mut attributes = Vec::new();
+		for &name in &["brightness", "actual_brightness"] {
+			let file = match File::open(path.join(name)) {
+				Ok(file) => file,
+				Err(_) if name == "actual_brightness" && !path.join(name).exists() => continue,
+				Err(err) => return Err(Error::from_io(err, &path.join(name))),
+			};
+			let mut attribute = Attribute { name, file, value: 0 };
+			attribute.value = read(&path, &attribute)?;
+			attributes.push(attribute);
+		}
+		Ok(Watcher {
+			path,
+			attributes,
+			interval: Duration::from_millis(500),
+			pending: VecDeque::new(),
+		})
+	}
+
+	/// The directory of the device being watched.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Set how often the attributes are read when the driver does not
+	/// notify changes.
+	pub fn set_interval(&mut self, interval: Duration) {
+		self.interval = interval.max(Duration::from_millis(1));
+	}
+
+	/// Return how often the attributes are read when the driver does not
+	/// notify changes.
+	pub fn interval(&self) -> Duration {
+		self.interval
+	}
+
+	/// Wait until at least one attribute changes and return the changes,
+	/// or return an empty list if `timeout` passes first.  With a timeout
+	/// of `None`, wait as long as it takes.
+	pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Vec<Change>, Error> {
+		if !self.pending.is_empty() {
+			return Ok(self.pending.drain(..).collect());
+		}
+		let deadline = timeout.map(|timeout| Instant::now() + timeout);
+		loop {
+			let mut wait = self.interval;
+			if let Some(deadline) = deadline {
+				let now = Instant::now();
+				if now >= deadline {
+					return Ok(Vec::new());
+				}
+				wait = wait.min(deadline - now);
+			}
+			self.poll(wait)?;
+
+			let changes = self.changes()?;
+			if !changes.is_empty() {
+				return Ok(changes);
+			}
+		}
+	}
+
+	/// Sleep for up to `timeout`, waking early if the driver notifies a
+	/// change on any attribute.
+	fn poll(&self, timeout: Duration) -> Result<(), Error> {
+		let mut fds: Vec<libc::pollfd> = self.attributes.iter().map(|attribute| libc::pollfd {
+			fd: attribute.file.as_raw_fd(),
+			events: libc::POLLPRI | libc::POLLERR,
+			revents: 0,
+		}).collect();
+		let millis = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+		// The descriptors stay open for as long as `self` is borrowed.
+		let result = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, millis) };
+		if result < 0 {
+			let err = std::io::Error::last_os_error();
+			if err.kind() != std::io::ErrorKind::Interrupted {
+				return Err(Error::Io(err));
+			}
+		}
+		Ok(())
+	}
+
+	/// Read every attribute again and return those whose value changed.
+	/// Reading also re-arms the notification.
+	fn changes(&mut self) -> Result<Vec<Change>, Error> {
+		let mut changes = Vec::new();
+		for attribute in &mut self.attributes {
+			let value = match read(&self.path, attribute) {
+				Ok(value) => value,
+				// Caught in the middle of being rewritten; look again next time.
+				Err(Error::MalformedAttribute { ref content, .. }) if content.is_empty() => continue,
+				Err(err) => return Err(err),
+			};
+			if value != attribute.value {
+				attribute.value = value;
+				changes.push(match attribute.name {
+					"brightness" => Change::Brightness(value),
+					_ => Change::ActualBrightness(value),
+				});
+			}
+		}
+		Ok(changes)
+	}
+}
+
+impl Iterator for Watcher {
+	type Item = Result<Change, Error>;
+
+	/// Wait for the next change.  Several changes seen at once are
+	/// returned one after the other, brightness first.
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.pending.is_empty() {
+			match self.wait(None) {
+				Ok(changes) => self.pending.extend(changes),
+				Err(err) => return Some(Err(err)),
+			}
+		}
+		self.pending.pop_front().map(Ok)
+	}
+}
+
+/// Read an attribute from the start of its open file.
+fn read(dir: &Path, attribute: &Attribute) -> Result<i32, Error> {
+	let path = dir.join(attribute.name);
+	let mut buffer = [0; 32];
+	let length = match attribute.file.read_at(&mut buffer, 0) {
+		Ok(length) => length,
+		Err(err) => return Err(Error::from_io(err, &path)),
+	};
+	let content = String::from_utf8_lossy(&buffer[..length]).trim().to_string();
+	match content.parse() {
+		Ok(value) => Ok(value),
+		Err(_) => Err(Error::MalformedAttribute { path, content }),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::thread;
+	use testing::FakeSysfs;
+	use {BacklightType, Brightness};
+
+	// Regular files do not support sysfs_notify(), so these exercise the
+	// polling fallback.
+
+	#[test]
+	fn wait_reports_changes_or_times_out() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let lcd = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+		let mut watcher = Brightness::with_root(sysfs.backlight_root(), "lcd").watch().unwrap();
+		watcher.set_interval(Duration::from_millis(10));
+
+		let timeout = Some(Duration::from_millis(50));
+		assert_eq!(watcher.wait(timeout).unwrap(), vec![]);
+
+		lcd.write("brightness", 40);
+		lcd.write("actual_brightness", 40);
+		assert_eq!(watcher.wait(timeout).unwrap(), vec![Change::Brightness(40), Change::ActualBrightness(40)]);
+
+		lcd.write("actual_brightness", 35);
+		assert_eq!(watcher.wait(timeout).unwrap(), vec![Change::ActualBrightness(35)]);
+	}
+
+	#[test]
+	fn leds_only_report_brightness() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let led = sysfs.add_led("kbd_backlight", 3).unwrap();
+		let mut watcher = Brightness::with_root(sysfs.leds_root(), "kbd_backlight").watch().unwrap();
+		watcher.set_interval(Duration::from_millis(10));
+
+		led.write("brightness", 2);
+		assert_eq!(watcher.wait(Some(Duration::from_secs(1))).unwrap(), vec![Change::Brightness(2)]);
+	}
+
+	#[test]
+	fn iterator_yields_changes_from_another_thread() {
+		let sysfs = FakeSysfs::new().unwrap();
+		let lcd = sysfs.add_backlight("lcd", BacklightType::Raw, 100).unwrap();
+		let mut watcher = Brightness::with_root(sysfs.backlight_root(), "lcd").watch().unwrap();
+		watcher.set_interval(Duration::from_millis(5));
+
+		let writer = thread::spawn(move || {
+			for level in 1..4 {
+				thread::sleep(Duration::from_millis(30));
+				lcd.write("brightness", level * 10);
+			}
+		});
+		let changes: Vec<Change> = watcher.by_ref().take(3).map(Result::unwrap).collect();
+		writer.join().unwrap();
+		assert_eq!(changes, vec![Change::Brightness(10), Change::Brightness(20), Change::Brightness(30)]);
+	}
+}